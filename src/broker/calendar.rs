@@ -0,0 +1,412 @@
+//! KRX 영업일 달력.
+//! 실시간 JIF 없이도 백테스트와 스케줄링에서 장 운영 시간을 알 수 있게 한다.
+
+use std::collections::{BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use tracing::warn;
+
+use crate::broker::session::SessionPhase;
+
+// KRX 휴장일 (주말 제외). 연말 휴장일(12/31) 포함.
+const KRX_HOLIDAYS: [(i32, u32, u32); 67] = [
+    // 2024
+    (2024, 1, 1),
+    (2024, 2, 9),
+    (2024, 2, 12),
+    (2024, 3, 1),
+    (2024, 4, 10),
+    (2024, 5, 1),
+    (2024, 5, 6),
+    (2024, 5, 15),
+    (2024, 6, 6),
+    (2024, 8, 15),
+    (2024, 9, 16),
+    (2024, 9, 17),
+    (2024, 9, 18),
+    (2024, 10, 1),
+    (2024, 10, 3),
+    (2024, 10, 9),
+    (2024, 12, 25),
+    (2024, 12, 31),
+    // 2025
+    (2025, 1, 1),
+    (2025, 1, 27),
+    (2025, 1, 28),
+    (2025, 1, 29),
+    (2025, 1, 30),
+    (2025, 3, 3),
+    (2025, 5, 1),
+    (2025, 5, 5),
+    (2025, 5, 6),
+    (2025, 6, 3),
+    (2025, 6, 6),
+    (2025, 8, 15),
+    (2025, 10, 3),
+    (2025, 10, 6),
+    (2025, 10, 7),
+    (2025, 10, 8),
+    (2025, 10, 9),
+    (2025, 12, 25),
+    (2025, 12, 31),
+    // 2026
+    (2026, 1, 1),
+    (2026, 2, 16),
+    (2026, 2, 17),
+    (2026, 2, 18),
+    (2026, 3, 2),
+    (2026, 5, 1),
+    (2026, 5, 5),
+    (2026, 5, 25),
+    (2026, 6, 3),
+    (2026, 8, 17),
+    (2026, 9, 24),
+    (2026, 9, 25),
+    (2026, 10, 5),
+    (2026, 10, 9),
+    (2026, 12, 25),
+    (2026, 12, 31),
+    // 2027
+    (2027, 1, 1),
+    (2027, 2, 8),
+    (2027, 2, 9),
+    (2027, 3, 1),
+    (2027, 5, 5),
+    (2027, 5, 13),
+    (2027, 8, 16),
+    (2027, 9, 14),
+    (2027, 9, 15),
+    (2027, 9, 16),
+    (2027, 10, 4),
+    (2027, 10, 11),
+    (2027, 12, 27),
+    (2027, 12, 31),
+];
+
+// KRX_HOLIDAYS 가 담고 있는 해
+const KRX_YEARS: RangeInclusive<i32> = 2024..=2027;
+
+// 대학수학능력시험일. 개장 10:00, 폐장 16:30 으로 한 시간씩 늦춘다.
+const EXAM_DAYS: [(i32, u32, u32); 4] = [
+    (2024, 11, 14),
+    (2025, 11, 13),
+    (2026, 11, 19),
+    (2027, 11, 18),
+];
+
+/// 하루의 정규장 시간. 장전 동시호가는 `open` 30분 전, 장마감 동시호가는 `close` 10분 전부터.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SessionHours {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+impl SessionHours {
+    pub fn new(open: NaiveTime, close: NaiveTime) -> Self {
+        Self { open, close }
+    }
+
+    fn regular() -> Self {
+        Self::new(hm(9, 0), hm(15, 30))
+    }
+
+    fn phase(&self, at: NaiveTime) -> SessionPhase {
+        if at < self.open - Duration::minutes(30) {
+            SessionPhase::Closed
+        } else if at < self.open {
+            SessionPhase::PreOpenAuction
+        } else if at < self.close - Duration::minutes(10) {
+            SessionPhase::Continuous
+        } else if at < self.close {
+            SessionPhase::ClosingAuction
+        } else if at < self.close + Duration::minutes(10) {
+            SessionPhase::Closed
+        } else if at < self.close + Duration::minutes(150) {
+            SessionPhase::AfterHours
+        } else {
+            SessionPhase::Closed
+        }
+    }
+}
+
+fn hm(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+fn ymd((year, month, day): (i32, u32, u32)) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+/// 휴장일과 특별 운영일을 담은 달력.
+/// 등록되지 않은 해는 주말만 쉬는 것으로 본다.
+#[derive(Clone, Debug, Default)]
+pub struct TradingCalendar {
+    holidays: BTreeSet<NaiveDate>,
+    special: HashMap<NaiveDate, SessionHours>,
+    //휴장일을 아는 해. 벗어난 날짜를 물으면 해마다 한 번 경고한다.
+    years: Option<RangeInclusive<i32>>,
+    warned: Arc<Mutex<BTreeSet<i32>>>,
+}
+
+impl TradingCalendar {
+    /// 주말만 쉬는 빈 달력
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 2024 ~ 2027 KRX 휴장일과 특별 운영일
+    pub fn krx() -> Self {
+        let mut calendar = Self::new()
+            .with_holidays(KRX_HOLIDAYS.into_iter().map(ymd))
+            .with_years(KRX_YEARS);
+        // 연초 개장일은 10:00 에 연다.
+        for year in KRX_YEARS {
+            let first = calendar.next_trading_day(ymd((year - 1, 12, 31)));
+            calendar = calendar.with_session(first, SessionHours::new(hm(10, 0), hm(15, 30)));
+        }
+        for day in EXAM_DAYS {
+            calendar = calendar.with_session(ymd(day), SessionHours::new(hm(10, 0), hm(16, 30)));
+        }
+        calendar
+    }
+
+    pub fn with_holidays(mut self, dates: impl IntoIterator<Item = NaiveDate>) -> Self {
+        self.holidays.extend(dates);
+        self
+    }
+
+    /// 휴장일을 등록한 해. 범위 밖의 날짜는 주말만 쉬는 것으로 보고 경고를 남긴다.
+    pub fn with_years(mut self, years: RangeInclusive<i32>) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// 개장/폐장 시간이 다른 날 (반일장, 지연 개장 등)
+    pub fn with_session(mut self, date: NaiveDate, hours: SessionHours) -> Self {
+        self.special.insert(date, hours);
+        self
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        self.check_year(date.year());
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+    }
+
+    // 휴장일을 모르는 해면 해마다 한 번만 경고한다.
+    fn check_year(&self, year: i32) {
+        let Some(years) = &self.years else {
+            return;
+        };
+        if !years.contains(&year) && self.warned.lock().unwrap().insert(year) {
+            warn!(
+                "trading calendar has no holidays for {}, only weekends are closed",
+                year
+            );
+        }
+    }
+
+    /// 그날 정규장 시간. 휴장일이면 None.
+    pub fn hours(&self, date: NaiveDate) -> Option<SessionHours> {
+        if !self.is_trading_day(date) {
+            return None;
+        }
+        Some(
+            self.special
+                .get(&date)
+                .copied()
+                .unwrap_or_else(SessionHours::regular),
+        )
+    }
+
+    /// `at` (KST) 이 정규장 시간인지
+    pub fn is_open(&self, at: NaiveDateTime) -> bool {
+        self.hours(at.date())
+            .is_some_and(|hours| (hours.open..hours.close).contains(&at.time()))
+    }
+
+    /// 시간표로 본 `at` (KST) 의 장 운영 상태
+    pub fn phase(&self, at: NaiveDateTime) -> SessionPhase {
+        match self.hours(at.date()) {
+            Some(hours) => hours.phase(at.time()),
+            None => SessionPhase::Closed,
+        }
+    }
+
+    /// `after` 이후 (같은 시각 포함) 처음 정규장이 열리는 시각
+    pub fn next_open(&self, after: NaiveDateTime) -> NaiveDateTime {
+        if let Some(hours) = self.hours(after.date()) {
+            if after.time() <= hours.open {
+                return after.date().and_time(hours.open);
+            }
+        }
+        let date = self.next_trading_day(after.date());
+        date.and_time(self.hours(date).unwrap_or_else(SessionHours::regular).open)
+    }
+
+    /// `after` 이후 (같은 시각 제외) 처음 정규장이 끝나는 시각
+    pub fn next_close(&self, after: NaiveDateTime) -> NaiveDateTime {
+        if let Some(hours) = self.hours(after.date()) {
+            if after.time() < hours.close {
+                return after.date().and_time(hours.close);
+            }
+        }
+        let date = self.next_trading_day(after.date());
+        date.and_time(self.hours(date).unwrap_or_else(SessionHours::regular).close)
+    }
+
+    /// `at` (KST) 기준으로 정규장이 끝난 가장 최근 영업일. 일봉이 확정된 마지막 날.
+    pub fn last_closed_day(&self, at: NaiveDateTime) -> NaiveDate {
+        match self.hours(at.date()) {
+            Some(hours) if at.time() >= hours.close => at.date(),
+            _ => self.previous_trading_day(at.date()),
+        }
+    }
+
+    /// `date` 다음 영업일
+    pub fn next_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut date = date.succ_opt().unwrap();
+        while !self.is_trading_day(date) {
+            date = date.succ_opt().unwrap();
+        }
+        date
+    }
+
+    /// `date` 직전 영업일
+    pub fn previous_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut date = date.pred_opt().unwrap();
+        while !self.is_trading_day(date) {
+            date = date.pred_opt().unwrap();
+        }
+        date
+    }
+
+    /// `date` 에서 영업일로 `days` 일 전
+    pub fn trading_days_before(&self, date: NaiveDate, days: usize) -> NaiveDate {
+        (0..days).fold(date, |date, _| self.previous_trading_day(date))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(date: (i32, u32, u32), hour: u32, minute: u32) -> NaiveDateTime {
+        ymd(date).and_time(hm(hour, minute))
+    }
+
+    #[test]
+    fn test_holidays() {
+        let calendar = TradingCalendar::krx();
+        assert!(!calendar.is_trading_day(ymd((2026, 2, 17))));
+        assert!(!calendar.is_trading_day(ymd((2026, 10, 17))));
+        assert!(!calendar.is_trading_day(ymd((2025, 12, 31))));
+        assert!(calendar.is_trading_day(ymd((2026, 10, 16))));
+
+        // 추석 연휴와 개천절 대체휴일을 건너뛴다.
+        assert_eq!(
+            calendar.previous_trading_day(ymd((2026, 9, 28))),
+            ymd((2026, 9, 23))
+        );
+        assert_eq!(
+            calendar.next_trading_day(ymd((2026, 10, 2))),
+            ymd((2026, 10, 6))
+        );
+        assert_eq!(
+            calendar.trading_days_before(ymd((2026, 10, 16)), 5),
+            ymd((2026, 10, 8))
+        );
+
+        assert_eq!(
+            calendar.last_closed_day(at((2026, 10, 16), 15, 30)),
+            ymd((2026, 10, 16))
+        );
+        assert_eq!(
+            calendar.last_closed_day(at((2026, 10, 16), 15, 0)),
+            ymd((2026, 10, 15))
+        );
+        assert_eq!(
+            calendar.last_closed_day(at((2026, 10, 18), 12, 0)),
+            ymd((2026, 10, 16))
+        );
+
+        // 2027 설 연휴와 대체휴일
+        assert!(!calendar.is_trading_day(ymd((2027, 2, 9))));
+        assert_eq!(
+            calendar.next_trading_day(ymd((2027, 2, 5))),
+            ymd((2027, 2, 10))
+        );
+        assert!(calendar.is_open(at((2027, 1, 4), 10, 0)));
+        assert!(!calendar.is_open(at((2027, 1, 4), 9, 30)));
+    }
+
+    #[test]
+    fn test_uncovered_years() {
+        let calendar = TradingCalendar::krx();
+        assert!(calendar.warned.lock().unwrap().is_empty());
+        // 모르는 해는 평일을 영업일로 보고 한 번 경고한다.
+        assert!(calendar.is_trading_day(ymd((2028, 1, 3))));
+        assert!(calendar.is_trading_day(ymd((2028, 1, 4))));
+        assert_eq!(
+            calendar.warned.lock().unwrap().iter().collect::<Vec<_>>(),
+            vec![&2028]
+        );
+    }
+
+    #[test]
+    fn test_special_sessions() {
+        let calendar = TradingCalendar::krx();
+
+        // 연초 개장일
+        assert!(!calendar.is_open(at((2026, 1, 2), 9, 30)));
+        assert!(calendar.is_open(at((2026, 1, 2), 10, 0)));
+        assert_eq!(
+            calendar.next_open(at((2025, 12, 30), 16, 0)),
+            at((2026, 1, 2), 10, 0)
+        );
+
+        // 수능일은 10:00 ~ 16:30
+        let exam = (2026, 11, 19);
+        assert_eq!(
+            calendar.phase(at(exam, 9, 40)),
+            SessionPhase::PreOpenAuction
+        );
+        assert_eq!(calendar.phase(at(exam, 16, 0)), SessionPhase::Continuous);
+        assert_eq!(
+            calendar.phase(at(exam, 16, 25)),
+            SessionPhase::ClosingAuction
+        );
+        assert_eq!(calendar.phase(at(exam, 17, 0)), SessionPhase::AfterHours);
+        assert_eq!(calendar.next_close(at(exam, 15, 30)), at(exam, 16, 30));
+    }
+
+    #[test]
+    fn test_phase() {
+        let calendar = TradingCalendar::krx();
+        let day = (2026, 10, 16);
+        for (hour, minute, phase) in [
+            (3, 0, SessionPhase::Closed),
+            (8, 30, SessionPhase::PreOpenAuction),
+            (9, 0, SessionPhase::Continuous),
+            (15, 19, SessionPhase::Continuous),
+            (15, 20, SessionPhase::ClosingAuction),
+            (15, 35, SessionPhase::Closed),
+            (16, 30, SessionPhase::AfterHours),
+            (18, 0, SessionPhase::Closed),
+        ] {
+            assert_eq!(calendar.phase(at(day, hour, minute)), phase);
+        }
+        assert_eq!(
+            calendar.phase(at((2026, 10, 17), 10, 0)),
+            SessionPhase::Closed
+        );
+        assert_eq!(calendar.next_open(at(day, 3, 0)), at(day, 9, 0));
+        assert_eq!(calendar.next_open(at(day, 9, 1)), at((2026, 10, 19), 9, 0));
+        assert_eq!(calendar.next_close(at(day, 3, 0)), at(day, 15, 30));
+        assert_eq!(
+            calendar.next_close(at(day, 15, 30)),
+            at((2026, 10, 19), 15, 30)
+        );
+    }
+}
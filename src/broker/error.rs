@@ -0,0 +1,151 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// 브로커 호출 실패 원인.
+/// `anyhow::Error` 에 담겨 오므로 `downcast_ref::<BrokerError>()` 로 꺼내 쓴다.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BrokerError {
+    //토큰 발급 실패, 만료, 앱키 오류
+    Auth(String),
+    //초당 전송 건수 초과
+    RateLimited(String),
+    //주문가능금액/수량 부족
+    InsufficientFunds(String),
+    //없는 종목, 거래 불가 종목
+    InvalidTicker(String),
+    //장 운영시간이 아님
+    MarketClosed(String),
+    //그 밖의 업무 거부
+    Rejected { code: String, message: String },
+    //네트워크, 응답 형식 오류
+    Transport(String),
+}
+
+impl BrokerError {
+    /// LS 응답의 `rsp_cd`/`rsp_msg` 를 해석한다. 정상 응답이면 None.
+    pub fn from_response(code: &str, message: &str) -> Option<Self> {
+        let code = code.trim();
+        let message = message.trim().to_string();
+        // 숫자 코드는 01000 미만이 정상 처리 (00000, 00039, 00040 ...)
+        if code.is_empty() || code.parse::<u32>().is_ok_and(|c| c < 1000) {
+            return None;
+        }
+
+        if let Some(gateway) = code.strip_prefix("IGW") {
+            return Some(match gateway {
+                "00201" | "00215" => BrokerError::RateLimited(message),
+                _ if gateway.starts_with("001") => BrokerError::Auth(message),
+                _ => BrokerError::Rejected {
+                    code: code.to_string(),
+                    message,
+                },
+            });
+        }
+
+        let compact: String = message.split_whitespace().collect();
+        let contains = |words: &[&str]| words.iter().any(|w| compact.contains(w));
+        Some(if contains(&["초당", "전송건수"]) {
+            BrokerError::RateLimited(message)
+        } else if contains(&["증거금", "주문가능금액", "주문가능수량", "잔고"])
+            && contains(&["부족", "초과"])
+        {
+            BrokerError::InsufficientFunds(message)
+        } else if contains(&["장종료", "장개시", "장운영시간", "주문가능시간", "시간외"])
+        {
+            BrokerError::MarketClosed(message)
+        } else if contains(&["종목"]) && contains(&["없", "존재하지", "확인", "정지"]) {
+            BrokerError::InvalidTicker(message)
+        } else {
+            BrokerError::Rejected {
+                code: code.to_string(),
+                message,
+            }
+        })
+    }
+
+    /// 잠시 후 같은 요청을 다시 보내면 성공할 수 있는 오류.
+    /// 네트워크 오류는 요청이 처리됐을 수 있어 넣지 않는다. 주문이면 먼저 접수 여부를 확인한다.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, BrokerError::RateLimited(_))
+    }
+}
+
+impl Display for BrokerError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BrokerError::Auth(msg) => write!(f, "authentication failed: {}", msg),
+            BrokerError::RateLimited(msg) => write!(f, "rate limited: {}", msg),
+            BrokerError::InsufficientFunds(msg) => write!(f, "insufficient funds: {}", msg),
+            BrokerError::InvalidTicker(msg) => write!(f, "invalid ticker: {}", msg),
+            BrokerError::MarketClosed(msg) => write!(f, "market closed: {}", msg),
+            BrokerError::Rejected { code, message } => {
+                write!(f, "rejected [{}]: {}", code, message)
+            }
+            BrokerError::Transport(msg) => write!(f, "transport error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BrokerError {}
+
+impl From<reqwest::Error> for BrokerError {
+    fn from(e: reqwest::Error) -> Self {
+        BrokerError::Transport(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_response() {
+        assert_eq!(BrokerError::from_response("00000", "정상처리"), None);
+        assert_eq!(
+            BrokerError::from_response("00040", "매수주문이 완료되었습니다."),
+            None
+        );
+        assert_eq!(BrokerError::from_response("", ""), None);
+
+        assert!(matches!(
+            BrokerError::from_response("IGW00121", "유효하지 않은 token 입니다."),
+            Some(BrokerError::Auth(_))
+        ));
+        assert!(matches!(
+            BrokerError::from_response("IGW00201", "초당 전송건수를 초과하였습니다."),
+            Some(BrokerError::RateLimited(_))
+        ));
+        assert!(matches!(
+            BrokerError::from_response("02714", "주문가능금액이 부족합니다."),
+            Some(BrokerError::InsufficientFunds(_))
+        ));
+        assert!(matches!(
+            BrokerError::from_response("01490", "장 종료 되었습니다."),
+            Some(BrokerError::MarketClosed(_))
+        ));
+        assert!(matches!(
+            BrokerError::from_response("02703", "종목코드를 확인하십시오."),
+            Some(BrokerError::InvalidTicker(_))
+        ));
+        assert_eq!(
+            BrokerError::from_response("03563", "호가단위 오류입니다."),
+            Some(BrokerError::Rejected {
+                code: "03563".to_string(),
+                message: "호가단위 오류입니다.".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(
+            BrokerError::RateLimited("초당 전송건수를 초과하였습니다.".to_string()).is_retryable()
+        );
+        // 주문이 들어갔을 수 있으니 그대로 다시 보내지 않는다.
+        assert!(!BrokerError::Transport("500 Internal Server Error".to_string()).is_retryable());
+        assert!(
+            !BrokerError::InsufficientFunds("주문가능금액이 부족합니다.".to_string())
+                .is_retryable()
+        );
+    }
+}
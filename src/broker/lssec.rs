@@ -5,15 +5,17 @@ use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Once};
 use std::thread::sleep;
+use std::time::{Duration, Instant};
 use std::{fmt, time};
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use diesel::row::NamedRow;
-use futures_util::stream::SplitSink;
+use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{future, pin_mut, FutureExt, SinkExt, StreamExt, TryFutureExt, TryStreamExt};
-use moka::future::Cache;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use serde::de::{self, Deserializer};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use teloxide::dptree::di::DependencySupplier;
@@ -21,33 +23,256 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::Mutex;
-use tokio::sync::OnceCell;
 use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite;
 use tokio_tungstenite::tungstenite::handshake::client::generate_key;
 use tokio_tungstenite::tungstenite::http;
 use tokio_tungstenite::{
     connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::broker;
+use crate::broker::error::BrokerError;
+use crate::broker::price_rules;
+use crate::broker::recorder::FrameRecorder;
+use crate::broker::scheduler::Scheduler;
+use crate::broker::session::{kst_now, parse_session, SessionEvent};
+use crate::broker::tradability::{parse_vi, Tradability, TradabilityEvent, TradabilityTracker};
 use crate::broker::{
-    Broker, Market, Order, OrderAction, OrderResult, OrderResultType, OrderType, Position, Tick,
+    BookLevel, Broker, Candle, CandleInterval, ConnectionEvent, ConnectionState, Fill, Instrument,
+    InstrumentKind, Market, Order, OrderAction, OrderBook, OrderFilter, OrderResult,
+    OrderResultType, OrderScope, OrderStatus, OrderType, Position, PriceSign, StreamKind, Tick,
+    TimeInForce, TradeSide,
 };
 
 static INIT: Once = Once::new();
 
+const API_URL: &str = "https://openapi.ls-sec.co.kr:8080";
+const WS_URL: &str = "wss://openapi.ls-sec.co.kr:9443/websocket";
+// 모의투자는 REST 는 같은 주소를 쓰고 웹소켓 포트만 다르다.
+const MOCK_WS_URL: &str = "wss://openapi.ls-sec.co.kr:29443/websocket";
+
+/// 접속 환경. 클라이언트 생성 시 고른다.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Environment {
+    //실투자
+    Production,
+    //모의투자
+    Mock,
+    //직접 지정 (테스트용 대역 서버 등)
+    Custom { api_url: String, ws_url: String },
+}
+
+impl Environment {
+    fn api_url(&self) -> &str {
+        match self {
+            Environment::Production | Environment::Mock => API_URL,
+            Environment::Custom { api_url, .. } => api_url.trim_end_matches('/'),
+        }
+    }
+
+    fn ws_url(&self) -> &str {
+        match self {
+            Environment::Production => WS_URL,
+            Environment::Mock => MOCK_WS_URL,
+            Environment::Custom { ws_url, .. } => ws_url,
+        }
+    }
+
+    pub fn is_mock(&self) -> bool {
+        matches!(self, Environment::Mock)
+    }
+}
+
+impl TryFrom<&str> for Environment {
+    type Error = anyhow::Error;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name.to_lowercase().as_str() {
+            "production" | "prod" => Ok(Environment::Production),
+            "mock" => Ok(Environment::Mock),
+            _ => Err(anyhow::anyhow!("Invalid environment: {}", name)),
+        }
+    }
+}
+
+type WsSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+type WsRead = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+
+// 만료 전에 미리 갱신할 여유 시간
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Debug)]
+struct AccessToken {
+    value: String,
+    refresh_at: Instant,
+}
+
+/// REST TR 응답. 본문과 함께 스케줄러 대기열에서 기다린 시간을 담는다.
+#[derive(Debug)]
+pub struct ApiResponse {
+    pub body: Value,
+    pub queue_wait: Duration,
+    /// 응답 헤더 `tr_cont` 가 "Y" 이면 다음 페이지 요청에 넣을 `tr_cont_key`
+    pub tr_cont_key: Option<String>,
+}
+
+impl ApiResponse {
+    fn new(tr_cd: &str, reply: Reply, queue_wait: Duration) -> Self {
+        let response = Self {
+            body: reply.body,
+            queue_wait,
+            tr_cont_key: reply.tr_cont_key,
+        };
+        if response.queue_wait >= Duration::from_secs(1) {
+            warn!("{} waited {:?} for rate limit", tr_cd, response.queue_wait);
+        }
+        response
+    }
+}
+
+impl Deref for ApiResponse {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.body
+    }
+}
+
+struct Reply {
+    status: StatusCode,
+    body: Value,
+    tr_cont_key: Option<String>,
+}
+
+/// 연속조회 TR 설정. OutBlock 의 cts 필드를 다음 요청 InBlock 에 옮겨 담는다.
+struct Paging {
+    in_block: &'static str,
+    out_block: &'static str,
+    /// 페이지마다 이어 붙일 목록 블록
+    list_block: &'static str,
+    cts_fields: &'static [&'static str],
+}
+
+const T0424_PAGING: Paging = Paging {
+    in_block: "t0424InBlock",
+    out_block: "t0424OutBlock",
+    list_block: "t0424OutBlock1",
+    cts_fields: &["cts_expcode"],
+};
+
+const T0425_PAGING: Paging = Paging {
+    in_block: "t0425InBlock",
+    out_block: "t0425OutBlock",
+    list_block: "t0425OutBlock1",
+    cts_fields: &["cts_ordno"],
+};
+
+// 연속 여부는 tr_cont 헤더로만 알려준다.
+const CSPAQ13700_PAGING: Paging = Paging {
+    in_block: "CSPAQ13700InBlock1",
+    out_block: "CSPAQ13700OutBlock1",
+    list_block: "CSPAQ13700OutBlock3",
+    cts_fields: &[],
+};
+
+const T8410_PAGING: Paging = Paging {
+    in_block: "t8410InBlock",
+    out_block: "t8410OutBlock",
+    list_block: "t8410OutBlock1",
+    cts_fields: &["cts_date"],
+};
+
+const T1405_PAGING: Paging = Paging {
+    in_block: "t1405InBlock",
+    out_block: "t1405OutBlock",
+    list_block: "t1405OutBlock1",
+    cts_fields: &["cts_shcode"],
+};
+
+const T8412_PAGING: Paging = Paging {
+    in_block: "t8412InBlock",
+    out_block: "t8412OutBlock",
+    list_block: "t8412OutBlock1",
+    cts_fields: &["cts_date", "cts_time"],
+};
+
+// 차트 TR 한 번에 받을 최대 건수
+const CHART_QUERY_COUNT: i64 = 2000;
+
+// 매매정지 종목을 다시 조회하는 간격
+const HALT_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+// 서버가 계속 같은 페이지를 주는 경우를 막는다.
+const MAX_PAGES: usize = 100;
+
+// ELW 매매수량단위. t8431 에는 수량단위가 없다.
+const ELW_LOT: i64 = 10;
+
+// 종목 마스터를 다시 받는 시각 (KST). 장 시작 전에 전일 종가, 상/하한가가 바뀐다.
+const MASTER_REFRESH_HOUR: u32 = 8;
+
+struct InstrumentCache {
+    fetched_at: NaiveDateTime,
+    instruments: Arc<HashMap<String, Instrument>>,
+}
+
+impl InstrumentCache {
+    // 마지막 갱신 시각이 지난 뒤에 받은 마스터인지
+    fn is_fresh(&self, now: NaiveDateTime) -> bool {
+        let refresh = now.date().and_hms_opt(MASTER_REFRESH_HOUR, 0, 0).unwrap();
+        let refresh = if now >= refresh {
+            refresh
+        } else {
+            refresh - chrono::Duration::days(1)
+        };
+        self.fetched_at >= refresh
+    }
+}
+
+/// 웹소켓 재접속 정책
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// 이 시간 동안 시세 프레임이 없으면 끊긴 것으로 보고 재접속한다.
+    pub stale_timeout: Option<Duration>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            stale_timeout: Some(Duration::from_secs(120)),
+        }
+    }
+}
+
 pub struct LsSecClient {
     key: String,
     secret: String,
-    token: Arc<String>,
+    environment: Environment,
+    token: Arc<Mutex<Option<AccessToken>>>,
     api: Client,
-    cache: Cache<String, String>,
     connect_socket: AtomicBool,
-    tickers: Arc<OnceCell<HashMap<String, Market>>>,
-    ws_sender: Arc<Mutex<Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>>,
-    tick_channels: Arc<Mutex<HashMap<String, Sender<Tick>>>>,
+    instruments: Arc<Mutex<Option<InstrumentCache>>>,
+    // 종목별 상장일. 바뀌지 않으므로 마스터를 다시 받아도 유지한다.
+    listing_dates: Arc<Mutex<HashMap<String, Option<NaiveDate>>>>,
+    ws_sender: Arc<Mutex<Option<WsSink>>>,
+    // 실시간 등록된 종목과 tr_cd. 재접속 시 다시 등록한다.
+    tick_channels: Arc<Mutex<HashMap<String, String>>>,
+    book_channels: Arc<Mutex<HashMap<String, String>>>,
+    order_books: broadcast::Sender<OrderBook>,
+    reconnect: ReconnectPolicy,
+    events: broadcast::Sender<ConnectionEvent>,
+    scheduler: Arc<Scheduler>,
+    recorder: Option<Arc<FrameRecorder>>,
+    sessions: broadcast::Sender<SessionEvent>,
+    tradability: TradabilityTracker,
+    tradability_events: broadcast::Sender<TradabilityEvent>,
 }
 
 impl Clone for LsSecClient {
@@ -55,13 +280,23 @@ impl Clone for LsSecClient {
         LsSecClient {
             key: self.key.clone(),
             secret: self.secret.clone(),
+            environment: self.environment.clone(),
             token: Arc::clone(&self.token),
             api: self.api.clone(),
-            cache: self.cache.clone(),
             connect_socket: AtomicBool::new(self.connect_socket.load(Ordering::Relaxed)),
-            tickers: Arc::clone(&self.tickers),
+            instruments: Arc::clone(&self.instruments),
+            listing_dates: Arc::clone(&self.listing_dates),
             ws_sender: Arc::clone(&self.ws_sender),
             tick_channels: Arc::clone(&self.tick_channels),
+            book_channels: Arc::clone(&self.book_channels),
+            order_books: self.order_books.clone(),
+            reconnect: self.reconnect.clone(),
+            events: self.events.clone(),
+            scheduler: Arc::clone(&self.scheduler),
+            recorder: self.recorder.clone(),
+            sessions: self.sessions.clone(),
+            tradability: self.tradability.clone(),
+            tradability_events: self.tradability_events.clone(),
         }
     }
 }
@@ -72,17 +307,57 @@ impl LsSecClient {
         Self {
             key,
             secret,
-            token: Default::default(),
+            environment: Environment::Production,
+            token: Arc::new(Mutex::new(None)),
             api: client,
-            cache: Cache::new(10_000),
             connect_socket: AtomicBool::new(false),
-            tickers: Arc::new(OnceCell::new()),
+            instruments: Arc::new(Mutex::new(None)),
+            listing_dates: Arc::new(Mutex::new(HashMap::new())),
             ws_sender: Arc::new(Mutex::new(None)),
             tick_channels: Arc::new(Mutex::new(HashMap::new())),
+            book_channels: Arc::new(Mutex::new(HashMap::new())),
+            order_books: broadcast::channel(1024).0,
+            reconnect: ReconnectPolicy::default(),
+            events: broadcast::channel(16).0,
+            scheduler: Arc::new(Scheduler::default()),
+            recorder: None,
+            sessions: broadcast::channel(16).0,
+            tradability: TradabilityTracker::new(),
+            tradability_events: broadcast::channel(256).0,
+        }
+    }
+
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// TR 별 호출 제한을 바꾼다. 기본값은 `Scheduler::default()`.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Arc::new(scheduler);
+        self
+    }
+
+    // 하루 한 번 마스터를 다시 받는다. 받는 동안 잠금을 잡아 한 번만 요청한다.
+    async fn instruments(&self) -> Result<Arc<HashMap<String, Instrument>>> {
+        let mut cache = self.instruments.lock().await;
+        let now = kst_now();
+        if let Some(cache) = cache.as_ref().filter(|cache| cache.is_fresh(now)) {
+            return Ok(Arc::clone(&cache.instruments));
         }
+        let instruments = Arc::new(self.fetch_instruments().await?);
+        *cache = Some(InstrumentCache {
+            fetched_at: now,
+            instruments: Arc::clone(&instruments),
+        });
+        Ok(instruments)
     }
 
-    async fn fetch_tickers(&self) -> Result<HashMap<String, Market>> {
+    async fn fetch_instruments(&self) -> Result<HashMap<String, Instrument>> {
         let result = self
             .api_call(
                 "/stock/etc",
@@ -101,61 +376,446 @@ impl LsSecClient {
             .as_array()
             .context("t8436OutBlock is not an array")?;
 
-        let mut tickers = HashMap::new();
+        // 모르는 구분값이 섞여 있어도 나머지 종목은 쓸 수 있게 건너뛴다.
+        let mut instruments = HashMap::new();
         for item in list {
-            let shcode = item
-                .get("shcode")
-                .and_then(|v| v.as_str())
-                .context("shcode not found or not a string")?;
+            match parse_instrument(item) {
+                Ok(instrument) => {
+                    instruments.insert(instrument.ticker.clone(), instrument);
+                }
+                Err(e) => warn!("skip t8436 row {}: {:#}", item, e),
+            }
+        }
 
-            let gubun = item
-                .get("gubun")
-                .and_then(|v| v.as_str())
-                .context("gubun not found or not a string")?;
+        // ELW 는 t8436 에 없어서 따로 받는다. 실패해도 주식 마스터는 쓴다.
+        match self.fetch_elws().await {
+            Ok(elws) => instruments.extend(elws.into_iter().map(|elw| (elw.ticker.clone(), elw))),
+            Err(e) => warn!("Failed to fetch ELW master: {:#}", e),
+        }
 
-            let market = Market::try_from(gubun).context("Failed to parse market")?;
+        Ok(instruments)
+    }
 
-            tickers.insert(shcode.to_string(), market);
+    async fn fetch_elws(&self) -> Result<Vec<Instrument>> {
+        let result = self
+            .api_call(
+                "/stock/elw",
+                "t8431",
+                &serde_json::json!({
+                    "t8431InBlock": {
+                        "dummy": ""
+                    }
+                }),
+            )
+            .await?;
+
+        let list = result
+            .get("t8431OutBlock")
+            .context("t8431OutBlock not found in response")?
+            .as_array()
+            .context("t8431OutBlock is not an array")?;
+        Ok(list
+            .iter()
+            .filter_map(|item| {
+                parse_elw(item)
+                    .map_err(|e| warn!("skip t8431 row {}: {:#}", item, e))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// t1102 상장일. 모르면 None
+    pub async fn get_listing_date(&self, ticker: &str) -> Result<Option<NaiveDate>> {
+        let result = self
+            .api_call(
+                "/stock/market-data",
+                "t1102",
+                &serde_json::json!({
+                    "t1102InBlock": {
+                        "shcode": ticker
+                    }
+                }),
+            )
+            .await?;
+        let listdate = result
+            .get("t1102OutBlock")
+            .context("t1102OutBlock not found in response")?
+            .get("listdate")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .trim();
+        if listdate.is_empty() || listdate.chars().all(|c| c == '0') {
+            return Ok(None);
         }
+        let date = NaiveDate::parse_from_str(listdate, "%Y%m%d")
+            .with_context(|| format!("Invalid listdate: {}", listdate))?;
+        Ok(Some(date))
+    }
 
-        Ok(tickers)
+    // 종목마다 한 번만 상장일을 조회한다. 실패하면 None 으로 두고 다음에 다시 조회한다.
+    async fn listing_date(&self, instrument: &Instrument) -> Option<NaiveDate> {
+        // t1102 는 주식 현재가 TR 이라 ELW 는 조회하지 않는다.
+        if instrument.kind == InstrumentKind::Elw {
+            return None;
+        }
+        if let Some(date) = self.listing_dates.lock().await.get(&instrument.ticker) {
+            return *date;
+        }
+        match self.get_listing_date(&instrument.ticker).await {
+            Ok(date) => {
+                self.listing_dates
+                    .lock()
+                    .await
+                    .insert(instrument.ticker.clone(), date);
+                date
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to fetch listing date of {}: {:#}",
+                    instrument.ticker, e
+                );
+                None
+            }
+        }
     }
 
+    /// t1405 매매정지 종목
+    pub async fn get_halted_tickers(&self) -> Result<Vec<String>> {
+        let rows = self
+            .api_call_paged(
+                "/stock/market-data",
+                "t1405",
+                &serde_json::json!({
+                    "t1405InBlock": {
+                        "gubun": "0",
+                        "jongchk": "2",
+                        "cts_shcode": ""
+                    }
+                }),
+                &T1405_PAGING,
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| row.get("shcode")?.as_str())
+            .map(|ticker| ticker.trim().to_string())
+            .collect())
+    }
+
+    /// 매매정지 목록을 다시 받아 상태를 갱신하고 바뀐 종목을 알린다.
+    pub async fn refresh_halts(&self) -> Result<()> {
+        let halted = self.get_halted_tickers().await?;
+        for event in self.tradability.set_halted(&halted) {
+            info!("{} {:?}", event.ticker, event.tradability);
+            let _ = self.tradability_events.send(event);
+        }
+        Ok(())
+    }
+
+    /// 체결/주문 웹소켓으로 받은 프레임을 모두 녹화한다.
+    pub fn with_recorder(mut self, recorder: FrameRecorder) -> Self {
+        self.recorder = Some(Arc::new(recorder));
+        self
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    fn notify(&self, stream: StreamKind, state: ConnectionState) {
+        let _ = self.events.send(ConnectionEvent {
+            stream,
+            state,
+            at: chrono::Utc::now(),
+        });
+    }
+
+    async fn add_realtime(
+        &self,
+        channels: &Mutex<HashMap<String, String>>,
+        ticker: &str,
+        tr_cd: &str,
+    ) -> Result<()> {
+        let mut channels = channels.lock().await;
+        if channels.contains_key(ticker) {
+            return Err(anyhow::anyhow!("Already subscribed"));
+        }
+
+        // 연결이 끊긴 동안에는 기록만 해두고 재접속 시 다시 등록한다.
+        let mut sender = self.ws_sender.lock().await;
+        match sender.as_mut() {
+            Some(sender) => {
+                let token = self.get_access_token().await?;
+                sender
+                    .send(subscribe_message(&token, "3", tr_cd, ticker))
+                    .await?;
+            }
+            None => info!("{} {} will be subscribed on connect", tr_cd, ticker),
+        }
+        channels.insert(ticker.to_string(), tr_cd.to_string());
+        Ok(())
+    }
+
+    async fn remove_realtime(
+        &self,
+        channels: &Mutex<HashMap<String, String>>,
+        ticker: &str,
+    ) -> Result<()> {
+        let mut channels = channels.lock().await;
+        let tr_cd = channels.remove(ticker).context("Not subscribed")?;
 
-    async fn api_call(&self, path: &str, tr_cd: &str, body: &Value) -> Result<Value> {
+        let mut sender = self.ws_sender.lock().await;
+        if let Some(sender) = sender.as_mut() {
+            let token = self.get_access_token().await?;
+            sender
+                .send(subscribe_message(&token, "4", &tr_cd, ticker))
+                .await?;
+        }
+        Ok(())
+    }
+
+    // 웹소켓에 접속하고 기록된 실시간 등록을 다시 보낸다.
+    async fn open_socket(&self, kind: StreamKind) -> Result<WsRead> {
+        let (ws_stream, _) = connect_async(self.environment.ws_url()).await?;
+        let (mut write, read) = ws_stream.split();
         let token = self.get_access_token().await?;
+
+        match kind {
+            StreamKind::Tick => {
+                let ticks = self.tick_channels.lock().await;
+                let books = self.book_channels.lock().await;
+                let mut sender = self.ws_sender.lock().await;
+                // 장운영정보, VI 는 종목과 상관없이 항상 받는다.
+                write
+                    .send(subscribe_message(&token, "3", "JIF", "0"))
+                    .await?;
+                write
+                    .send(subscribe_message(&token, "3", "VI_", "000000"))
+                    .await?;
+                for (ticker, tr_cd) in ticks.iter().chain(books.iter()) {
+                    write
+                        .send(subscribe_message(&token, "3", tr_cd, ticker))
+                        .await?;
+                }
+                *sender = Some(write);
+            }
+            StreamKind::OrderTransaction => {
+                for tr_cd in &["SC0", "SC1", "SC2", "SC3", "SC4"] {
+                    write
+                        .send(subscribe_message(&token, "1", tr_cd, ""))
+                        .await?;
+                }
+            }
+        }
+        Ok(read)
+    }
+
+    // 수신이 끊기거나 멈추면 지수 백오프로 재접속한다.
+    async fn run_socket<T, P>(
+        self,
+        kind: StreamKind,
+        mut read: WsRead,
+        token: CancellationToken,
+        tx: Sender<T>,
+        parse: P,
+    ) where
+        T: Send + 'static,
+        P: Fn(&Value) -> Option<T> + Send + 'static,
+    {
+        let stale_timeout = match kind {
+            StreamKind::Tick => self.reconnect.stale_timeout,
+            StreamKind::OrderTransaction => None,
+        };
+
+        loop {
+            loop {
+                let frame = tokio::select! {
+                    frame = next_frame(&mut read, stale_timeout) => frame,
+                    _ = token.cancelled() => {
+                        info!("receive signal");
+                        return;
+                    }
+                };
+                match frame {
+                    Some(Ok(message)) => {
+                        let text = message.to_string();
+                        if let Some(recorder) = &self.recorder {
+                            recorder.record(kind, &text);
+                        }
+                        let Ok(json) = serde_json::from_str::<Value>(&text) else {
+                            continue;
+                        };
+                        if let Some(item) = parse(&json) {
+                            if tx.send(item).await.is_err() {
+                                info!("{:?} receiver dropped", kind);
+                                return;
+                            }
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("{:?} websocket error: {}", kind, e);
+                        break;
+                    }
+                    None => break,
+                }
+            }
+
+            if kind == StreamKind::Tick {
+                *self.ws_sender.lock().await = None;
+            }
+            warn!("{:?} websocket disconnected", kind);
+            self.notify(kind, ConnectionState::Disconnected);
+
+            let mut backoff = self.reconnect.initial_backoff;
+            read = loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = token.cancelled() => {
+                        info!("receive signal");
+                        return;
+                    }
+                }
+                match self.open_socket(kind).await {
+                    Ok(read) => break read,
+                    Err(e) => {
+                        error!("Failed to reconnect {:?} websocket: {}", kind, e);
+                        backoff = (backoff * 2).min(self.reconnect.max_backoff);
+                    }
+                }
+            };
+            info!("{:?} websocket reconnected", kind);
+            self.notify(kind, ConnectionState::Reconnected);
+        }
+    }
+
+    async fn api_call(&self, path: &str, tr_cd: &str, body: &Value) -> Result<ApiResponse> {
+        self.api_call_cont(path, tr_cd, body, None).await
+    }
+
+    async fn api_call_cont(
+        &self,
+        path: &str,
+        tr_cd: &str,
+        body: &Value,
+        tr_cont_key: Option<&str>,
+    ) -> Result<ApiResponse> {
+        let token = self.get_access_token().await?;
+        let mut queue_wait = self.scheduler.acquire(tr_cd).await;
+        let mut reply = self
+            .send_request(path, tr_cd, body, &token, tr_cont_key)
+            .await?;
+        if is_token_rejected(reply.status, &reply.body) {
+            // 토큰이 만료/폐기됐으면 새로 받아 한 번만 재시도한다.
+            warn!("access token rejected on {}, refreshing", tr_cd);
+            self.invalidate_token(&token).await;
+            let token = self.get_access_token().await?;
+            queue_wait += self.scheduler.acquire(tr_cd).await;
+            reply = self
+                .send_request(path, tr_cd, body, &token, tr_cont_key)
+                .await?;
+        }
+
+        check_response(&reply)?;
+        Ok(ApiResponse::new(tr_cd, reply, queue_wait))
+    }
+
+    /// 연속조회로 모든 페이지를 받아 `paging.list_block` 목록을 이어 붙인다.
+    async fn api_call_paged(
+        &self,
+        path: &str,
+        tr_cd: &str,
+        body: &Value,
+        paging: &Paging,
+    ) -> Result<Vec<Value>> {
+        let mut body = body.clone();
+        let mut tr_cont_key: Option<String> = None;
+        let mut items = Vec::new();
+
+        for _ in 0..MAX_PAGES {
+            let response = self
+                .api_call_cont(path, tr_cd, &body, tr_cont_key.as_deref())
+                .await?;
+            match response.get(paging.list_block) {
+                Some(Value::Array(list)) => items.extend(list.iter().cloned()),
+                Some(Value::Null) | None => {}
+                Some(item) => items.push(item.clone()),
+            }
+
+            // 마지막 페이지에도 cts 가 채워져 오므로 (t0425, t8410 등) 끝은 tr_cont 로만 판단한다.
+            if response.tr_cont_key.is_none() {
+                return Ok(items);
+            }
+            for field in paging.cts_fields {
+                body[paging.in_block][*field] = response
+                    .get(paging.out_block)
+                    .and_then(|block| block.get(*field))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+            }
+            tr_cont_key = response.tr_cont_key.clone();
+        }
+
+        warn!("{} continuation exceeded {} pages", tr_cd, MAX_PAGES);
+        Ok(items)
+    }
+
+    async fn send_request(
+        &self,
+        path: &str,
+        tr_cd: &str,
+        body: &Value,
+        token: &str,
+        tr_cont_key: Option<&str>,
+    ) -> Result<Reply> {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert("Authorization", format!("Bearer {}", token).parse()?);
         headers.insert("tr_cd", tr_cd.parse()?);
-        headers.insert("tr_cont", "N".parse()?);
+        match tr_cont_key {
+            Some(key) => {
+                headers.insert("tr_cont", "Y".parse()?);
+                headers.insert("tr_cont_key", key.parse()?);
+            }
+            None => {
+                headers.insert("tr_cont", "N".parse()?);
+            }
+        }
 
-        self.api
-            .post(format!("https://openapi.ls-sec.co.kr:8080{}", path))
+        let response = self
+            .api
+            .post(format!("{}{}", self.environment.api_url(), path))
             .headers(headers)
             .json(body)
             .send()
-            .await?
+            .await
+            .map_err(BrokerError::from)?;
+        let status = response.status();
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let tr_cont_key = match header("tr_cont").as_deref() {
+            Some("Y") => Some(header("tr_cont_key").unwrap_or_default()),
+            _ => None,
+        };
+        let body = response
             .json()
             .await
-            .context("Failed to parse API response")
+            .map_err(|e| BrokerError::Transport(format!("Failed to parse API response: {}", e)))?;
+        Ok(Reply {
+            status,
+            body,
+            tr_cont_key,
+        })
     }
-}
-
-#[async_trait]
-impl Broker for LsSecClient {
-    async fn get_tickers(&self) -> Result<HashMap<String, Market>> {
-        let result = self.tickers.get_or_try_init(|| self.fetch_tickers()).await;
-        result.cloned()
-    }
-
-    async fn get_access_token(&self) -> Result<String> {
-        if let Some(token) = self.cache.get("access_token").await {
-            return Ok(token.trim_matches('"').to_string());
-        }
 
+    async fn issue_token(&self) -> Result<AccessToken> {
         let result = self
             .api
-            .post("	https://openapi.ls-sec.co.kr:8080/oauth2/token")
+            .post(format!("{}/oauth2/token", self.environment.api_url()))
             .form(&[
                 ("grant_type", "client_credentials"),
                 ("appkey", &self.key),
@@ -163,254 +823,946 @@ impl Broker for LsSecClient {
                 ("scope", "oob"),
             ])
             .send()
-            .await?
+            .await
+            .map_err(BrokerError::from)?
             .json::<Value>()
-            .await?;
+            .await
+            .map_err(BrokerError::from)?;
 
-        let token = result
+        let value = result
             .get("access_token")
             .and_then(|t| t.as_str())
-            .ok_or_else(|| anyhow::anyhow!("No access token"))?
+            .ok_or_else(|| {
+                let message = result
+                    .get("error_description")
+                    .or_else(|| result.get("rsp_msg"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("No access token");
+                BrokerError::Auth(message.to_string())
+            })?
             .trim_matches('"')
             .to_string();
+        let expires_in = result
+            .get("expires_in")
+            .cloned()
+            .and_then(|v| string_or_number(v).ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(86400);
 
-        self.cache
-            .insert("access_token".to_string(), token.clone())
-            .await;
+        let lifetime = Duration::from_secs(expires_in as u64);
+        let margin = TOKEN_REFRESH_MARGIN.min(lifetime / 10);
+        Ok(AccessToken {
+            value,
+            refresh_at: Instant::now() + lifetime - margin,
+        })
+    }
+
+    // 다른 요청이 이미 갱신했을 수 있으니 같은 토큰일 때만 버린다.
+    async fn invalidate_token(&self, rejected: &str) {
+        let mut token = self.token.lock().await;
+        if token.as_ref().is_some_and(|t| t.value == rejected) {
+            *token = None;
+        }
+    }
+
+    /// 발급받은 접근 토큰을 폐기한다. 종료 시 호출한다.
+    pub async fn revoke_token(&self) -> Result<()> {
+        let Some(token) = self.token.lock().await.take() else {
+            return Ok(());
+        };
+        let response = self
+            .api
+            .post(format!("{}/oauth2/revoke", self.environment.api_url()))
+            .form(&[
+                ("appkey", self.key.as_str()),
+                ("appsecretkey", self.secret.as_str()),
+                ("token_type_hint", "access_token"),
+                ("token", token.value.as_str()),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Failed to revoke token: {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+// rsp_cd 가 오류면 BrokerError 로 바꾼다.
+fn check_response(reply: &Reply) -> std::result::Result<(), BrokerError> {
+    let field = |name: &str| reply.body.get(name).and_then(|v| v.as_str()).unwrap_or("");
+    if let Some(e) = BrokerError::from_response(field("rsp_cd"), field("rsp_msg")) {
+        return Err(e);
+    }
+    match reply.status {
+        StatusCode::TOO_MANY_REQUESTS => Err(BrokerError::RateLimited(reply.status.to_string())),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(BrokerError::Auth(reply.status.to_string()))
+        }
+        status if !status.is_success() => Err(BrokerError::Transport(status.to_string())),
+        _ => Ok(()),
+    }
+}
+
+// LS 는 만료된 토큰에 401 또는 IGW00121 응답을 준다.
+fn is_token_rejected(status: StatusCode, body: &Value) -> bool {
+    status == StatusCode::UNAUTHORIZED
+        || body.get("rsp_cd").and_then(|v| v.as_str()) == Some("IGW00121")
+}
+
+fn subscribe_message(token: &str, tr_type: &str, tr_cd: &str, tr_key: &str) -> Message {
+    Message::text(
+        serde_json::json!({
+            "header": {
+                "token": token,
+                "tr_type": tr_type
+            },
+            "body": {
+                "tr_cd": tr_cd,
+                "tr_key": tr_key
+            }
+        })
+        .to_string(),
+    )
+}
+
+async fn next_frame(
+    read: &mut WsRead,
+    stale_timeout: Option<Duration>,
+) -> Option<Result<Message, tungstenite::Error>> {
+    match stale_timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, read.next()).await {
+            Ok(frame) => frame,
+            Err(_) => {
+                warn!("no websocket frame for {:?}", timeout);
+                None
+            }
+        },
+        None => read.next().await,
+    }
+}
+
+// LS 는 숫자를 문자열로 보내는 경우가 많아 둘 다 받는다.
+pub(crate) fn string_or_number<'de, D>(deserializer: D) -> std::result::Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Number(n) => n
+            .as_i64()
+            .or_else(|| n.as_f64().map(|f| f as i64))
+            .ok_or_else(|| de::Error::custom("invalid number")),
+        Value::String(s) if s.trim().is_empty() => Ok(0),
+        Value::String(s) => s.trim().parse().map_err(de::Error::custom),
+        v => Err(de::Error::custom(format!("unexpected value {}", v))),
+    }
+}
+
+// IsuNo 의 A/Q/J 접두어를 떼어 단축코드로
+fn short_code(isu_no: &str) -> &str {
+    let isu_no = isu_no.trim();
+    match isu_no.strip_prefix(['A', 'Q', 'J']) {
+        Some(code) if code.len() == 6 => code,
+        _ => isu_no,
+    }
+}
+
+// S3_/K3_ 체결 본문
+#[derive(Deserialize)]
+struct RawTick {
+    shcode: String,
+    #[serde(deserialize_with = "string_or_number")]
+    price: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    cvolume: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    volume: i64,
+    chetime: String,
+    #[serde(deserialize_with = "string_or_number")]
+    change: i64,
+    sign: String,
+    #[serde(deserialize_with = "string_or_number")]
+    open: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    high: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    low: i64,
+    cgubun: String,
+}
+
+impl TryFrom<RawTick> for Tick {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawTick) -> Result<Self> {
+        let sign = PriceSign::try_from(raw.sign.as_str())?;
+        let change = match sign {
+            PriceSign::Down | PriceSign::LowerLimit => -raw.change.abs(),
+            _ => raw.change.abs(),
+        };
+        Ok(Tick {
+            ticker: raw.shcode,
+            price: raw.price,
+            volume: raw.cvolume,
+            cumulative_volume: raw.volume,
+            time: NaiveTime::parse_from_str(&raw.chetime, "%H%M%S")
+                .with_context(|| format!("invalid chetime {}", raw.chetime))?,
+            change,
+            sign,
+            open: raw.open,
+            high: raw.high,
+            low: raw.low,
+            side: TradeSide::from(raw.cgubun.as_str()),
+            received_at: chrono::Utc::now(),
+        })
+    }
+}
+
+pub(crate) fn parse_tick(json: &Value) -> Option<Tick> {
+    let raw = json
+        .get("body")
+        .and_then(|body| serde_json::from_value::<RawTick>(body.clone()).ok())?;
+    Tick::try_from(raw)
+        .map_err(|e| error!("Failed to parse tick: {}", e))
+        .ok()
+}
+
+// 주문번호는 REST 응답(OrdNo)과 맞추기 위해 앞의 0을 떼어낸다.
+fn order_no(body: &Value, field: &str) -> Option<String> {
+    body.get(field)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim_start_matches('0').to_string())
+        .filter(|s| !s.is_empty())
+}
+
+pub(crate) fn parse_order_book(json: &Value) -> Option<OrderBook> {
+    let tr_cd = json.get("header")?.get("tr_cd")?.as_str()?;
+    if tr_cd != "H1_" && tr_cd != "HA_" {
+        return None;
+    }
+    // 구독 응답은 body 가 null 이다.
+    let body = json.get("body").filter(|body| !body.is_null())?;
+    let number = |name: String| -> Result<i64> {
+        let value = body.get(&name).cloned().unwrap_or(Value::Null);
+        string_or_number(value).with_context(|| format!("invalid {}", name))
+    };
+    let parse = || -> Result<OrderBook> {
+        let mut asks = Vec::with_capacity(10);
+        let mut bids = Vec::with_capacity(10);
+        for level in 1..=10 {
+            asks.push(BookLevel {
+                price: number(format!("offerho{}", level))?,
+                quantity: number(format!("offerrem{}", level))?,
+            });
+            bids.push(BookLevel {
+                price: number(format!("bidho{}", level))?,
+                quantity: number(format!("bidrem{}", level))?,
+            });
+        }
+        let hotime = body.get("hotime").and_then(|v| v.as_str()).unwrap_or("");
+        Ok(OrderBook {
+            ticker: body
+                .get("shcode")
+                .and_then(|v| v.as_str())
+                .context("shcode not found")?
+                .to_string(),
+            time: NaiveTime::parse_from_str(hotime, "%H%M%S")
+                .with_context(|| format!("invalid hotime {}", hotime))?,
+            asks,
+            bids,
+            total_ask_quantity: number("totofferrem".to_string())?,
+            total_bid_quantity: number("totbidrem".to_string())?,
+            received_at: chrono::Utc::now(),
+        })
+    };
+    parse()
+        .map_err(|e| error!("Failed to parse order book: {}", e))
+        .ok()
+}
+
+pub(crate) fn parse_order_result(json: &Value) -> Option<OrderResult> {
+    let trcd = json.get("header").and_then(|header| header.get("tr_cd"))?;
+    let body = json.get("body")?;
+    let result = match trcd.as_str() {
+        Some("SC0") => OrderResultType::Wait,
+        Some("SC1") => {
+            return parse_fill(body)
+                .map_err(|e| error!("Failed to parse execution: {}", e))
+                .ok();
+        }
+        Some("SC2") => OrderResultType::Edit,
+        Some("SC3") => OrderResultType::Cancel,
+        Some("SC4") => OrderResultType::Denied,
+        _ => return None,
+    };
+    let mut order_result = OrderResult::new(order_no(body, "ordno")?, result);
+    // 정정/취소 확인은 새 주문번호와 함께 원주문번호를 준다.
+    order_result.original_id = order_no(body, "orgordno");
+    order_result.ticker = ["shtnIsuno", "shtcode"]
+        .iter()
+        .find_map(|field| body.get(*field).and_then(|v| v.as_str()))
+        .map(|code| short_code(code).to_string());
+    Some(order_result)
+}
+
+// t0425 주문/체결 내역 한 건
+#[derive(Deserialize)]
+struct RawOrder {
+    #[serde(deserialize_with = "string_or_number")]
+    ordno: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    orgordno: i64,
+    expcode: String,
+    medosu: String,
+    #[serde(deserialize_with = "string_or_number")]
+    qty: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    price: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    cheqty: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    cheprice: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    ordrem: i64,
+    status: String,
+    hogagb: String,
+}
+
+impl TryFrom<RawOrder> for Order {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawOrder) -> Result<Self> {
+        // medosu 는 "매도"/"매수" 로 온다.
+        let action = match raw.medosu.trim() {
+            side if side.contains("매도") => OrderAction::Sell,
+            side if side.contains("매수") => OrderAction::Buy,
+            code => OrderAction::try_from(code)?,
+        };
+        let status = if raw.status.contains("거부") {
+            OrderStatus::Rejected
+        } else if raw.status.contains("취소") {
+            OrderStatus::Cancelled
+        } else if raw.status.contains("정정") {
+            OrderStatus::Amended
+        } else {
+            fill_status(raw.qty, raw.cheqty)
+        };
+        let mut order = Order::new(
+            raw.ordno,
+            raw.expcode,
+            raw.qty,
+            raw.price,
+            action,
+            OrderType::try_from(raw.hogagb.as_str())?,
+        );
+        order.original_id = Some(raw.orgordno).filter(|no| *no > 0);
+        order.status = status;
+        order.filled_quantity = raw.cheqty;
+        order.remaining_quantity = if status.is_open() { raw.ordrem } else { 0 };
+        order.filled_price = raw.cheprice;
+        Ok(order)
+    }
+}
+
+// CSPAQ13700 주문체결내역 한 건
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawExecution {
+    #[serde(deserialize_with = "string_or_number")]
+    ord_no: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    org_ord_no: i64,
+    isu_no: String,
+    bns_tp_code: String,
+    ordprc_ptn_code: String,
+    #[serde(deserialize_with = "string_or_number")]
+    ord_qty: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    ord_prc: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    exec_qty: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    exec_prc: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    canc_cnf_qty: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    rjt_qty: i64,
+}
+
+impl TryFrom<RawExecution> for Order {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawExecution) -> Result<Self> {
+        let status = if raw.rjt_qty > 0 {
+            OrderStatus::Rejected
+        } else if raw.canc_cnf_qty > 0 {
+            OrderStatus::Cancelled
+        } else {
+            fill_status(raw.ord_qty, raw.exec_qty)
+        };
+        let mut order = Order::new(
+            raw.ord_no,
+            short_code(&raw.isu_no).to_string(),
+            raw.ord_qty,
+            raw.ord_prc,
+            OrderAction::try_from(raw.bns_tp_code.as_str())?,
+            OrderType::try_from(raw.ordprc_ptn_code.as_str())?,
+        );
+        order.original_id = Some(raw.org_ord_no).filter(|no| *no > 0);
+        order.status = status;
+        order.filled_quantity = raw.exec_qty;
+        order.remaining_quantity = if status.is_open() {
+            raw.ord_qty - raw.exec_qty
+        } else {
+            0
+        };
+        order.filled_price = raw.exec_prc;
+        Ok(order)
+    }
+}
 
-        Ok(token)
+fn fill_status(quantity: i64, filled: i64) -> OrderStatus {
+    if quantity > 0 && filled >= quantity {
+        OrderStatus::Filled
+    } else if filled > 0 {
+        OrderStatus::PartiallyFilled
+    } else {
+        OrderStatus::Accepted
+    }
+}
+
+// 모르는 주문유형 등은 건너뛰고 나머지는 돌려준다.
+fn parse_orders<R>(rows: Vec<Value>, tr_cd: &str) -> Vec<Order>
+where
+    R: for<'de> Deserialize<'de>,
+    Order: TryFrom<R, Error = anyhow::Error>,
+{
+    rows.into_iter()
+        .filter_map(|row| {
+            serde_json::from_value::<R>(row)
+                .map_err(anyhow::Error::from)
+                .and_then(Order::try_from)
+                .map_err(|e| error!("Failed to parse {} order: {}", tr_cd, e))
+                .ok()
+        })
+        .collect()
+}
+
+// t8436 종목 한 줄
+#[derive(Deserialize)]
+struct RawInstrument {
+    shcode: String,
+    #[serde(default)]
+    expcode: String,
+    #[serde(default)]
+    hname: String,
+    gubun: String,
+    #[serde(default)]
+    etfgubun: String,
+    #[serde(default = "default_lot", deserialize_with = "string_or_number")]
+    memedan: i64,
+    #[serde(default, deserialize_with = "string_or_number")]
+    jnilclose: i64,
+    #[serde(default, deserialize_with = "string_or_number")]
+    uplmtprice: i64,
+    #[serde(default, deserialize_with = "string_or_number")]
+    dnlmtprice: i64,
+    //기업인수목적회사 여부 (Y/N)
+    #[serde(default)]
+    spac_gubun: String,
+}
+
+fn default_lot() -> i64 {
+    1
+}
+
+// t8436 에는 우선주 구분이 없어서 단축코드로 가른다.
+// KRX 는 보통주 단축코드 끝자리를 0 으로 주고, 같은 회사의 우선주는 끝자리만 바꿔 준다.
+// (1우 5, 2우B 7, 3우B 9, 2024 년 이후 신형 우선주 K, L, M ...)
+// 앞자리에 영문이 들어간 신규 코드 (0001A0) 도 끝자리 규칙은 같다. 스팩은 끝자리와 상관없이 보통주다.
+fn is_preferred(shcode: &str, spac: bool) -> bool {
+    !spac && !shcode.ends_with('0')
+}
+
+fn parse_instrument(row: &Value) -> Result<Instrument> {
+    let raw = RawInstrument::deserialize(row)?;
+    let market = Market::try_from(raw.gubun.as_str())?;
+    let kind = match raw.etfgubun.as_str() {
+        "1" => InstrumentKind::Etf,
+        "2" => InstrumentKind::Etn,
+        "" | "0" if is_preferred(&raw.shcode, raw.spac_gubun == "Y") => InstrumentKind::Preferred,
+        "" | "0" => InstrumentKind::Stock,
+        code => return Err(anyhow!("Invalid etfgubun: {}", code)),
+    };
+    Ok(Instrument {
+        ticker: raw.shcode,
+        isin: raw.expcode,
+        name: raw.hname,
+        market,
+        kind,
+        lot: raw.memedan.max(1),
+        // t8436 에는 상장일이 없다. 종목별로 조회할 때 t1102 로 채운다.
+        listing_date: None,
+        prev_close: raw.jnilclose,
+        upper_limit: raw.uplmtprice,
+        lower_limit: raw.dnlmtprice,
+    })
+}
+
+// t8431 ELW 종목 한 줄. ELW 는 유가증권시장 상장이다.
+#[derive(Deserialize)]
+struct RawElw {
+    shcode: String,
+    #[serde(default)]
+    expcode: String,
+    #[serde(default)]
+    hname: String,
+    #[serde(default, deserialize_with = "string_or_number")]
+    jnilclose: i64,
+    #[serde(default, deserialize_with = "string_or_number")]
+    uplmtprice: i64,
+    #[serde(default, deserialize_with = "string_or_number")]
+    dnlmtprice: i64,
+}
+
+fn parse_elw(row: &Value) -> Result<Instrument> {
+    let raw = RawElw::deserialize(row)?;
+    Ok(Instrument {
+        ticker: raw.shcode,
+        isin: raw.expcode,
+        name: raw.hname,
+        market: Market::KOSPI,
+        kind: InstrumentKind::Elw,
+        lot: ELW_LOT,
+        listing_date: None,
+        prev_close: raw.jnilclose,
+        upper_limit: raw.uplmtprice,
+        lower_limit: raw.dnlmtprice,
+    })
+}
+
+// t8410/t8412 차트 한 봉. 일봉에는 time 이 없다.
+#[derive(Deserialize)]
+struct RawCandle {
+    date: String,
+    #[serde(default)]
+    time: String,
+    #[serde(deserialize_with = "string_or_number")]
+    open: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    high: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    low: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    close: i64,
+    #[serde(deserialize_with = "string_or_number")]
+    jdiff_vol: i64,
+}
+
+fn parse_candle(ticker: &str, row: Value) -> Result<Candle> {
+    let raw: RawCandle = serde_json::from_value(row)?;
+    let date = NaiveDate::parse_from_str(&raw.date, "%Y%m%d")
+        .with_context(|| format!("invalid chart date {}", raw.date))?;
+    let time = if raw.time.trim().is_empty() {
+        NaiveTime::MIN
+    } else {
+        NaiveTime::parse_from_str(&raw.time, "%H%M%S")
+            .with_context(|| format!("invalid chart time {}", raw.time))?
+    };
+    Ok(Candle {
+        ticker: ticker.to_string(),
+        datetime: date.and_time(time),
+        open: raw.open,
+        high: raw.high,
+        low: raw.low,
+        close: raw.close,
+        volume: raw.jdiff_vol,
+    })
+}
+
+// SC1 체결 본문. 부분 체결이면 unercqty 가 남는다.
+fn parse_fill(body: &Value) -> Result<OrderResult> {
+    let field = |name: &str| body.get(name).and_then(|v| v.as_str()).unwrap_or("");
+    let number = |name: &str| -> Result<i64> {
+        let value = body.get(name).cloned().unwrap_or(Value::Null);
+        string_or_number(value).with_context(|| format!("invalid {}", name))
+    };
+    // exectime 은 HHMMSSmmm
+    let exectime = field("exectime");
+    let fill = Fill {
+        action: OrderAction::try_from(field("bnstp"))?,
+        quantity: number("execqty")?,
+        price: number("execprc")?,
+        time: NaiveTime::parse_from_str(exectime.get(..6).unwrap_or(exectime), "%H%M%S")
+            .with_context(|| format!("invalid exectime {}", exectime))?,
+        remaining: number("unercqty")?,
+    };
+    let id = order_no(body, "ordno").context("ordno not found")?;
+    let ticker = short_code(field("shtnIsuno")).to_string();
+    Ok(OrderResult::filled(id, ticker, fill))
+}
+
+#[async_trait]
+impl Broker for LsSecClient {
+    async fn get_instruments(&self) -> Result<HashMap<String, Instrument>> {
+        Ok(self.instruments().await?.as_ref().clone())
+    }
+
+    async fn get_instrument(&self, ticker: &str) -> Result<Instrument> {
+        let mut instrument = self
+            .instruments()
+            .await?
+            .get(ticker)
+            .cloned()
+            .ok_or_else(|| BrokerError::InvalidTicker(ticker.to_string()))?;
+        instrument.listing_date = self.listing_date(&instrument).await;
+        Ok(instrument)
+    }
+
+    async fn get_access_token(&self) -> Result<String> {
+        // 갱신 중에는 잠금을 잡고 있어 동시에 여러 번 발급받지 않는다.
+        let mut token = self.token.lock().await;
+        if let Some(current) = token.as_ref() {
+            if Instant::now() < current.refresh_at {
+                return Ok(current.value.clone());
+            }
+            info!("access token is about to expire, refreshing");
+        }
+
+        let issued = self.issue_token().await?;
+        let value = issued.value.clone();
+        *token = Some(issued);
+        Ok(value)
     }
 
     async fn connect_websocket_order_transaction(
         &self,
         token: CancellationToken,
     ) -> Result<Receiver<OrderResult>> {
-        let (ws_stream, _) = connect_async("wss://openapi.ls-sec.co.kr:9443/websocket").await?;
-        let (mut write, mut read) = ws_stream.split();
-
-        for i in &["SC0", "SC1", "SC2", "SC3", "SC4"] {
-            write
-                .send(Message::text(
-                    serde_json::json!({
-                        "header": {
-                            "token": self.get_access_token().await?,
-                            "tr_type": "1"
-                        },
-                        "body": {
-                            "tr_cd": i,
-                            "tr_key": ""
-                        }
-                    })
-                        .to_string(),
-                ))
-                .await?;
-        }
-        let (tx, rx) = channel::<OrderResult>(100);
-        tokio::spawn(async move {
-            tokio::select! {
-                _ = read.map(|msg| {
-                    if let Ok(message) = msg {
-                        if let Ok(json) = serde_json::from_str::<Value>(&message.to_string()) {
-                        if let Some(trcd) = json.get("header").and_then(|header| header.get("tr_cd")) {
-                         let orderNo = json
-                            .get("body")
-                            .and_then(|body| body.get("ordno"))
-                            .and_then(|ordno| ordno.as_str())
-                            .and_then(|s| Some(s.to_string()));
-                            match trcd.as_str() {
-                                Some("SC0") => {
-                                        if let Some(orderNo) = orderNo {
-                                            return Some(OrderResult{ id: orderNo, result: OrderResultType::Wait })
-                                        }
-                                },
-                                Some("SC1") => {
-                                        if let Some(orderNo) = orderNo {
-                                            return Some(OrderResult{ id: orderNo, result: OrderResultType::Success })
-                                        }
-                                },
-                                Some("SC2") => {
-                                        if let Some(orderNo) = orderNo {
-                                            return Some(OrderResult{ id: orderNo, result: OrderResultType::Edit })
-                                        }
-                                },
-                                Some("SC3") => {
-                                        if let Some(orderNo) = orderNo {
-                                            return Some(OrderResult{ id: orderNo, result: OrderResultType::Cancel })
-                                        }
-                                },
-                                Some("SC4") => {
-                                    if let Some(orderNo) = orderNo {
-                                            return Some(OrderResult{ id: orderNo, result: OrderResultType::Denied })
-                                        }
-                                }
-                                _ => {
-                                        println!("none")
-                                    }
-                            }
-                                }
-                        }
-                    }
-                    None
-                }).for_each(|msg| async {
-                    match msg {
-                        Some(message) => {
-                            tx.send(message).await.unwrap();
-                        },
-                        None => {
-                            info!("does not tick data")
-                        }
-                    }
+        let read = self.open_socket(StreamKind::OrderTransaction).await?;
+        self.notify(StreamKind::OrderTransaction, ConnectionState::Connected);
 
-                }) => {
-                    info!("stop receive websocket tick data")
-                }
-                _ = token.cancelled() => {
-                    info!("receive signal")
-                }
-            }
-        });
+        let (tx, rx) = channel::<OrderResult>(100);
+        tokio::spawn(self.clone().run_socket(
+            StreamKind::OrderTransaction,
+            read,
+            token,
+            tx,
+            parse_order_result,
+        ));
         Ok(rx)
     }
 
     async fn connect_websocket(&self, token: CancellationToken) -> Result<Receiver<Tick>> {
-        let (ws_stream, _) = connect_async("wss://openapi.ls-sec.co.kr:9443/websocket").await?;
-        let (write, mut read) = ws_stream.split();
-        *self.ws_sender.lock().await = Some(write);
+        let read = self.open_socket(StreamKind::Tick).await?;
+        self.notify(StreamKind::Tick, ConnectionState::Connected);
 
-        let (tx, rx) = channel::<Tick>(100);
+        // 호가, 장운영정보, VI 는 체결과 같은 소켓으로 들어오므로 여기서 나눠 보낸다.
+        let order_books = self.order_books.clone();
+        let sessions = self.sessions.clone();
+        let tradability = self.tradability.clone();
+        let tradability_events = self.tradability_events.clone();
+        let parse = move |json: &Value| {
+            if let Some(book) = parse_order_book(json) {
+                let _ = order_books.send(book);
+                return None;
+            }
+            if let Some(event) = parse_session(json) {
+                info!(
+                    "{} session {:?} ({})",
+                    event.market, event.phase, event.code
+                );
+                let _ = sessions.send(event);
+                return None;
+            }
+            if let Some(event) = parse_vi(json) {
+                info!("{} {:?}", event.ticker, event.tradability);
+                tradability.apply(&event);
+                let _ = tradability_events.send(event);
+                return None;
+            }
+            parse_tick(json)
+        };
+
+        // 매매정지는 실시간으로 오지 않으므로 주기적으로 조회한다.
+        let client = self.clone();
+        let halt_token = token.clone();
         tokio::spawn(async move {
-            tokio::select! {
-                _ = read.map(|msg| {
-                    if let Ok(message) = msg {
-                        if let Ok(json) = serde_json::from_str::<Value>(&message.to_string()) {
-                            if let Some(cd) = json.get("body").and_then(|body| {
-                                serde_json::from_value::<Tick>(body.clone()).ok()
-                            }) {
-                                return Some(cd)
-                            }
-                        }
-                    }
-                    None
-                }).for_each(|msg| async {
-                    match msg {
-                        Some(message) => {
-                            tx.send(message).await.unwrap();
-                        },
-                        None => {
-                            info!("does not tick data")
+            let mut interval = tokio::time::interval(HALT_REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(e) = client.refresh_halts().await {
+                            warn!("Failed to refresh halted tickers: {:#}", e);
                         }
                     }
-
-                }) => {
-                    info!("stop receive websocket tick data")
-                }
-                _ = token.cancelled() => {
-                    info!("receive signal")
+                    _ = halt_token.cancelled() => return,
                 }
             }
         });
+
+        let (tx, rx) = channel::<Tick>(100);
+        tokio::spawn(
+            self.clone()
+                .run_socket(StreamKind::Tick, read, token, tx, parse),
+        );
         Ok(rx)
     }
 
+    fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    fn session_events(&self) -> broadcast::Receiver<SessionEvent> {
+        self.sessions.subscribe()
+    }
+
+    fn tradability(&self, ticker: &str) -> Tradability {
+        self.tradability.get(ticker)
+    }
+
+    fn tradability_events(&self) -> broadcast::Receiver<TradabilityEvent> {
+        self.tradability_events.subscribe()
+    }
+
     async fn subscribe(&self, ticker: &str) -> Result<()> {
-        let mut channels = self.tick_channels.lock().await;
-        if !channels.contains_key(ticker) {
-            let mut sender = self.ws_sender.lock().await;
-            if sender.is_none() {
-                return Err(anyhow::Error::msg("No websocket connection"));
-            }
-            drop(sender);
-            let tickermap = self.get_tickers().await?;
-            let tickers = tickermap.get(ticker).context("invalid ticker")?;
-            let data = serde_json::json!({
-                "header": {
-                    "token": self.get_access_token().await?,
-                    "tr_type": "3"
-                },
-                "body": {
-                    "tr_cd": || -> &'static str {
-                match tickers {
-                    Market::KOSPI => "S3_",
-                    Market::KOSDAQ => "K3_",
-                }
-            }(),
-                    "tr_key": ticker
-                }
-            });
+        let instrument = self.get_instrument(ticker).await?;
+        let tr_cd = instrument
+            .tick_tr()
+            .with_context(|| format!("no realtime tick TR for {:?}", instrument))?;
+        self.add_realtime(&self.tick_channels, ticker, tr_cd).await
+    }
+
+    async fn unsubscribe(&self, ticker: &str) -> Result<()> {
+        self.remove_realtime(&self.tick_channels, ticker).await
+    }
 
-            let mut sender = self.ws_sender.lock().await;
-            if let Some(sender) = sender.as_mut() {
-                sender.send(Message::Text(data.to_string())).await?;
+    async fn subscribe_order_book(&self, ticker: &str) -> Result<()> {
+        let instrument = self.get_instrument(ticker).await?;
+        let tr_cd = instrument
+            .order_book_tr()
+            .with_context(|| format!("no realtime order book TR for {:?}", instrument))?;
+        self.add_realtime(&self.book_channels, ticker, tr_cd).await
+    }
+
+    async fn unsubscribe_order_book(&self, ticker: &str) -> Result<()> {
+        self.remove_realtime(&self.book_channels, ticker).await
+    }
+
+    fn order_book_stream(&self) -> broadcast::Receiver<OrderBook> {
+        self.order_books.subscribe()
+    }
+
+    async fn subscriptions(&self) -> Vec<String> {
+        self.tick_channels.lock().await.keys().cloned().collect()
+    }
+
+    async fn get_balance(&self) -> Result<i64> {
+        let result = self
+            .api_call(
+                "/stock/accno",
+                "CSPAQ12200",
+                &serde_json::json!({
+                    "CSPAQ12200InBlock": {
+                        "RecCnt": 1,
+                        "MgmtBrnNo": "1",
+                        "BalCreTp": "1"
+                    }
+                }),
+            )
+            .await?;
+        result
+            .get("CSPAQ12200OutBlock2")
+            .and_then(|block| block.get("MnyOrdAbleAmt"))
+            .and_then(|amt| amt.as_i64())
+            .context("Failed to get balance")
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>> {
+        let result = self
+            .api_call_paged(
+                "/stock/accno",
+                "t0424",
+                &serde_json::json!({
+                    "t0424InBlock": {
+                        "prcgb": "",
+                        "chegb": "",
+                        "dangb": "",
+                        "charge": "",
+                        "cts_expcode": ""
+                    }
+                }),
+                &T0424_PAGING,
+            )
+            .await?;
+
+        let positions = result
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<Position>, _>>()
+            .context("Failed to deserialize positions")?;
+
+        Ok(positions)
+    }
+
+    async fn get_candles(
+        &self,
+        ticker: &str,
+        interval: CandleInterval,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Candle>> {
+        let (sdate, edate) = (
+            from.format("%Y%m%d").to_string(),
+            to.format("%Y%m%d").to_string(),
+        );
+        let (tr_cd, body, paging) = match interval {
+            CandleInterval::Day => (
+                "t8410",
+                serde_json::json!({
+                    "t8410InBlock": {
+                        "shcode": ticker,
+                        "gubun": "2",
+                        "qrycnt": CHART_QUERY_COUNT,
+                        "sdate": sdate,
+                        "edate": edate,
+                        "cts_date": "",
+                        "comp_yn": "N",
+                        "sujung": "Y"
+                    }
+                }),
+                &T8410_PAGING,
+            ),
+            CandleInterval::Minute(minutes) => (
+                "t8412",
+                serde_json::json!({
+                    "t8412InBlock": {
+                        "shcode": ticker,
+                        "ncnt": minutes,
+                        "qrycnt": CHART_QUERY_COUNT,
+                        "nday": "0",
+                        "sdate": sdate,
+                        "stime": "",
+                        "edate": edate,
+                        "etime": "",
+                        "cts_date": "",
+                        "cts_time": "",
+                        "comp_yn": "N"
+                    }
+                }),
+                &T8412_PAGING,
+            ),
+        };
+        let rows = self
+            .api_call_paged("/stock/chart", tr_cd, &body, paging)
+            .await?;
+
+        // 최근 봉부터 페이지가 오므로 다시 정렬한다.
+        let mut candles = rows
+            .into_iter()
+            .map(|row| parse_candle(ticker, row))
+            .collect::<Result<Vec<_>>>()?;
+        candles.sort_by_key(|candle| candle.datetime);
+        candles.dedup_by_key(|candle| candle.datetime);
+        Ok(candles)
+    }
+
+    async fn order_cancel(&self, order: Order) -> Result<()> {
+        let instrument = self.get_instrument(&order.symbol).await?;
+        let body = serde_json::json!({
+            "CSPAT00801InBlock1": {  // 정확한 tr_cd를 사용해야 합니다. 여기서는 예시로 CSPAT00800을 사용했습니다.
+                "OrgOrdNo": order.id,
+                "IsuNo": instrument.isu_no(),
+                "OrdQty": order.quantity,
             }
-            Ok(())
-        } else {
-            return Err(anyhow::anyhow!("Already subscribed"));
-        }
+        });
+
+        self.api_call("/stock/order", "CSPAT00801", &body).await?; // tr_cd를 올바르게 수정해야 합니다.
+
+        Ok(())
     }
 
-    async fn get_balance(&self) -> Result<i64> {
-        let result = self
-            .api_call(
+    async fn get_orders(&self, filter: OrderFilter) -> Result<Vec<Order>> {
+        let chegb = match filter.scope {
+            OrderScope::All => "0",
+            OrderScope::Filled => "1",
+            OrderScope::Unfilled => "2",
+        };
+        let medosu = match filter.action {
+            None => "0",
+            Some(OrderAction::Sell) => "1",
+            Some(OrderAction::Buy) => "2",
+        };
+        let rows = self
+            .api_call_paged(
                 "/stock/accno",
-                "CSPAQ12200",
+                "t0425",
                 &serde_json::json!({
-                    "CSPAQ12200InBlock": {
-                        "RecCnt": 1,
-                        "MgmtBrnNo": "1",
-                        "BalCreTp": "1"
+                    "t0425InBlock": {
+                        "expcode": filter.ticker.clone().unwrap_or_default(),
+                        "chegb": chegb,
+                        "medosu": medosu,
+                        "sortgb": "2",
+                        "cts_ordno": ""
                     }
                 }),
+                &T0425_PAGING,
             )
             .await?;
-        result
-            .get("CSPAQ12200OutBlock2")
-            .and_then(|block| block.get("MnyOrdAbleAmt"))
-            .and_then(|amt| amt.as_i64())
-            .context("Failed to get balance")
+
+        Ok(parse_orders::<RawOrder>(rows, "t0425")
+            .into_iter()
+            .filter(|order| filter.matches(order))
+            .collect())
     }
 
-    async fn get_positions(&self) -> Result<Vec<Position>> {
-        let result = self
-            .api_call(
+    async fn get_executions(&self, date: NaiveDate) -> Result<Vec<Order>> {
+        let rows = self
+            .api_call_paged(
                 "/stock/accno",
-                "t0424",
+                "CSPAQ13700",
                 &serde_json::json!({
-                    "t0424InBlock": {
-                        "prcgb": "",
-                        "chegb": "",
-                        "dangb": "",
-                        "charge": "",
-                        "cts_expcode": ""
+                    "CSPAQ13700InBlock1": {
+                        "OrdMktCode": "00",
+                        "BnsTpCode": "0",
+                        "IsuNo": "",
+                        "ExecYn": "1",
+                        "OrdDt": date.format("%Y%m%d").to_string(),
+                        "SrtOrdNo2": 999999999,
+                        "BkseqTpCode": "0",
+                        "OrdPtnCode": "00"
                     }
                 }),
+                &CSPAQ13700_PAGING,
             )
             .await?;
 
-        let positions = result
-            .get("t0424OutBlock1")
-            .context("t0424OutBlock1 not found in response")?
-            .as_array()
-            .context("t0424OutBlock1 is not an array")?
-            .iter()
-            .map(|x| serde_json::from_value(x.clone()))
-            .collect::<Result<Vec<Position>, _>>()
-            .context("Failed to deserialize positions")?;
-
-        Ok(positions)
+        Ok(parse_orders::<RawExecution>(rows, "CSPAQ13700"))
     }
 
-    async fn order_cancel(&self, order: Order) -> Result<()> {
+    async fn order_modify(&self, order: Order, new_qty: i64, new_price: i64) -> Result<Order> {
+        let instrument = self.get_instrument(&order.symbol).await?;
+        if order.order_type.has_price() {
+            price_rules::validate_price(&instrument, new_price)?;
+        }
         let body = serde_json::json!({
-            "CSPAT00801InBlock1": {  // 정확한 tr_cd를 사용해야 합니다. 여기서는 예시로 CSPAT00800을 사용했습니다.
+            "CSPAT00701InBlock1": {
                 "OrgOrdNo": order.id,
-                "IsuNo": format!("A{}", order.symbol),
-                "OrdQty": order.quantity,
+                "IsuNo": instrument.isu_no(),
+                "OrdQty": new_qty,
+                "OrdprcPtnCode": order.order_type.as_str(),
+                "OrdCndiTpCode": order.time_in_force.as_str(),
+                "OrdPrc": if order.order_type.has_price() { new_price } else { 0 }
             }
         });
 
-        self.api_call("/stock/order", "CSPAT00801", &body).await?; // tr_cd를 올바르게 수정해야 합니다.
+        let result = self.api_call("/stock/order", "CSPAT00701", &body).await?;
+        let id = result
+            .get("CSPAT00701OutBlock2")
+            .and_then(|block| block.get("OrdNo"))
+            .and_then(|ord_no| ord_no.as_i64())
+            .context("Failed to get order number")?;
 
-        Ok(())
+        Ok(order.amended(id, new_qty, new_price))
     }
 
     async fn order(
@@ -420,22 +1772,42 @@ impl Broker for LsSecClient {
         price: i64,
         order_action: OrderAction,
         order_type: OrderType,
+        time_in_force: TimeInForce,
     ) -> Result<Order> {
+        order_type.validate(time_in_force, price)?;
+        // 모의투자는 지정가/시장가 일반 주문만 받는다.
+        if self.environment.is_mock()
+            && (!matches!(order_type, OrderType::Limit | OrderType::Market)
+                || time_in_force != TimeInForce::Day)
+        {
+            return Err(anyhow!(
+                "{:?} {:?} order is not supported in the mock environment",
+                order_type,
+                time_in_force
+            ));
+        }
+        let instrument = self.get_instrument(symbol).await?;
+        if order_type.has_price() {
+            price_rules::validate_price(&instrument, price)?;
+        }
+        if amount % instrument.lot != 0 {
+            return Err(anyhow!(
+                "order quantity {} is not a multiple of lot {} for {}",
+                amount,
+                instrument.lot,
+                symbol
+            ));
+        }
         let body = serde_json::json!({
             "CSPAT00601InBlock1": {
-                "IsuNo": format!("A{}", symbol),
+                "IsuNo": instrument.isu_no(),
                 "OrdQty": amount,
-                "OrdPrc": || -> i64 {
-                    match order_type {
-                        OrderType::Market => 0,
-                        OrderType::Limit => price,
-                    }
-                }(),
+                "OrdPrc": if order_type.has_price() { price } else { 0 },
                 "BnsTpCode": order_action.as_str(),
                 "OrdprcPtnCode": order_type.as_str(),
                 "MgntrnCode": "000",
                 "LoanDt": "",
-                "OrdCndiTpCode": "0"
+                "OrdCndiTpCode": time_in_force.as_str()
             }
         });
 
@@ -446,7 +1818,7 @@ impl Broker for LsSecClient {
             .and_then(|ord_no| ord_no.as_i64())
             .context("Failed to get order number")?;
 
-        let order = Order::new(
+        let mut order = Order::new(
             id,
             symbol.to_string(),
             amount,
@@ -454,6 +1826,7 @@ impl Broker for LsSecClient {
             order_action,
             order_type,
         );
+        order.time_in_force = time_in_force;
         Ok(order)
     }
 }
@@ -461,102 +1834,949 @@ impl Broker for LsSecClient {
 #[cfg(test)]
 mod test {
     use super::*;
-    use futures_util::future::join_all;
+    use crate::broker::mock::MockLsServer;
+    use crate::broker::recorder::read_frames;
+    use crate::broker::replay::{ReplayBroker, ReplaySpeed};
+    use crate::broker::scheduler::RateLimit;
+    use crate::broker::session::SessionPhase;
 
-    static KEY: &str = "PSA0cTqjeDE2hoNUclL2tiHgOLLQwzdkX43e";
-    static SECRET: &str = "H0jIqEYl6cqNGr7CyyfHfN2Ns1hPiRR7";
+    fn client(server: &MockLsServer) -> LsSecClient {
+        LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment())
+    }
 
     #[tokio::test]
     async fn test() {
-        let client = LsSecClient::new(KEY.to_string(), SECRET.to_string());
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+        let token = client.get_access_token().await.unwrap();
+        assert_eq!(client.get_access_token().await.unwrap(), token);
+        assert_eq!(server.issued_tokens().await, vec![token]);
+    }
+
+    #[tokio::test]
+    async fn test_api_call_queue_wait() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server).with_scheduler(Scheduler::new(RateLimit {
+            calls: 1,
+            per: Duration::from_millis(300),
+        }));
+        let body = serde_json::json!({ "CSPAQ12200InBlock": { "RecCnt": 1 } });
+
+        let first = client
+            .api_call("/stock/accno", "CSPAQ12200", &body)
+            .await
+            .unwrap();
+        assert!(first.queue_wait < Duration::from_millis(100));
+        let second = client
+            .api_call("/stock/accno", "CSPAQ12200", &body)
+            .await
+            .unwrap();
+        assert!(second.queue_wait >= Duration::from_millis(200));
+        assert_eq!(second["CSPAQ12200OutBlock2"]["MnyOrdAbleAmt"], 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_token_refresh_before_expiry() {
+        let server = MockLsServer::start().await.unwrap();
+        server.set_token_lifetime(1).await;
+        let client = client(&server);
+
+        let first = client.get_access_token().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(950)).await;
+        let second = client.get_access_token().await.unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_expired_token() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        client.get_balance().await.unwrap();
+        server.expire_tokens().await;
+        assert_eq!(client.get_balance().await.unwrap(), 1_000_000);
+        assert_eq!(server.issued_tokens().await.len(), 2);
+        assert_eq!(server.requests("CSPAQ12200").await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_token() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
         let token = client.get_access_token().await.unwrap();
-        // let token = client.get_access_token().await.unwrap();
-        println!("{:?}", token);
+        client.revoke_token().await.unwrap();
+        assert_eq!(server.revoked_tokens().await, vec![token.clone()]);
+        assert_ne!(client.get_access_token().await.unwrap(), token);
+    }
+
+    #[test]
+    fn test_parse_tick() {
+        let frame = serde_json::json!({
+            "header": {"tr_cd": "K3_", "tr_key": "092190"},
+            "body": {
+                "chetime": "101502", "sign": "5", "change": "35", "price": "4165",
+                "open": "4200", "high": "4230", "low": "4150", "cgubun": "-",
+                "cvolume": "12", "volume": "183021", "shcode": "092190"
+            }
+        });
+        let tick = parse_tick(&frame).unwrap();
+        assert_eq!(tick.price, 4165);
+        assert_eq!(tick.change, -35);
+        assert_eq!(tick.sign, PriceSign::Down);
+        assert_eq!(tick.side, TradeSide::Sell);
+        assert_eq!(tick.volume, 12);
+        assert_eq!(tick.cumulative_volume, 183021);
+        assert_eq!(tick.time, NaiveTime::from_hms_opt(10, 15, 2).unwrap());
+
+        // 단일가 체결은 cgubun 이 비어 있어도 버리지 않는다.
+        let mut auction = frame.clone();
+        auction["body"]["cgubun"] = serde_json::json!(" ");
+        assert_eq!(parse_tick(&auction).unwrap().side, TradeSide::Unknown);
+
+        let ack = serde_json::json!({
+            "header": {"tr_cd": "K3_", "tr_key": "092190", "rsp_cd": "00000"},
+            "body": null
+        });
+        assert!(parse_tick(&ack).is_none());
+    }
+
+    #[test]
+    fn test_parse_order_book_ack() {
+        for tr_cd in ["H1_", "HA_"] {
+            let ack = serde_json::json!({
+                "header": {"tr_cd": tr_cd, "tr_key": "005930", "rsp_cd": "00000"},
+                "body": null
+            });
+            assert!(parse_order_book(&ack).is_none());
+        }
+    }
+
+    #[test]
+    fn test_environment() {
+        let mock = Environment::try_from("mock").unwrap();
+        assert!(mock.is_mock());
+        assert_eq!(mock.api_url(), API_URL);
+        assert_eq!(mock.ws_url(), MOCK_WS_URL);
+        assert_eq!(
+            Environment::try_from("PRODUCTION").unwrap().ws_url(),
+            WS_URL
+        );
+        assert!(Environment::try_from("staging").is_err());
+
+        let custom = Environment::Custom {
+            api_url: "http://127.0.0.1:8080/".to_string(),
+            ws_url: "ws://127.0.0.1:9443/websocket".to_string(),
+        };
+        assert_eq!(custom.api_url(), "http://127.0.0.1:8080");
     }
 
     #[tokio::test]
     async fn test_get_positions() {
-        let client = LsSecClient::new(KEY.to_string(), SECRET.to_string());
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
         let positions = client.get_positions().await.expect("");
-        let result = positions.iter().find(|p| p.ticker == "030520").take();
-        match result {
-            Some(p) => {
-                println!("{}", p.ticker)
-            }
-            None => {
-                println!("None!")
-            }
+        let result = positions.iter().find(|p| p.ticker == "030520");
+        assert_eq!(result.map(|p| p.quantity), Some(10));
+    }
+
+    #[tokio::test]
+    async fn test_get_positions_paged() {
+        let server = MockLsServer::start().await.unwrap();
+        for ticker in ["005930", "005935", "092190", "000660"] {
+            server.add_position(ticker, 1).await;
         }
+        server.set_page_size(2).await;
+        let client = client(&server).with_scheduler(Scheduler::new(RateLimit::per_second(100)));
+
+        let positions = client.get_positions().await.unwrap();
+        let tickers: Vec<_> = positions.iter().map(|p| p.ticker.as_str()).collect();
+        assert_eq!(
+            tickers,
+            vec!["030520", "005930", "005935", "092190", "000660"]
+        );
+
+        let cts: Vec<_> = server
+            .requests("t0424")
+            .await
+            .iter()
+            .map(|body| body["t0424InBlock"]["cts_expcode"].clone())
+            .collect();
+        assert_eq!(cts, vec!["", "005935", "000660"]);
     }
 
     #[tokio::test]
     async fn test_get_balance() {
-        let client = LsSecClient::new(KEY.to_string(), SECRET.to_string());
-        let balance = client.get_balance().await;
-        println!("{:?}", balance)
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+        let balance = client.get_balance().await.unwrap();
+        assert_eq!(balance, 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_get_instruments() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+        for _ in 0..3 {
+            let map = client.get_instruments().await.unwrap();
+            assert_eq!(map["005930"].market, Market::KOSPI);
+            assert_eq!(map["005930"].kind, InstrumentKind::Stock);
+            assert_eq!(map["005930"].isin, "KR7005930003");
+            assert_eq!(map["005930"].name, "삼성전자");
+            assert_eq!(map["005930"].lot, 1);
+            assert_eq!(map["005935"].kind, InstrumentKind::Preferred);
+            assert_eq!(map["092190"].market, Market::KOSDAQ);
+            assert_eq!(map["069500"].kind, InstrumentKind::Etf);
+            assert_eq!(map["530031"].kind, InstrumentKind::Etn);
+            assert_eq!(map["580001"].kind, InstrumentKind::Elw);
+            assert_eq!(map["580001"].market, Market::KOSPI);
+            assert_eq!(map["580001"].lot, 10);
+            assert_eq!(map["216400"].market, Market::KONEX);
+            assert_eq!(map["216400"].tick_tr(), None);
+            // 마스터에는 상장일이 없다.
+            assert_eq!(map["005930"].listing_date, None);
+            // 모르는 시장 구분은 건너뛴다.
+            assert!(!map.contains_key("999990"));
+        }
+        assert_eq!(server.requests("t8436").await.len(), 1);
+        assert_eq!(server.requests("t8431").await.len(), 1);
+
+        // 갱신 시각이 지나면 다시 받는다.
+        client.instruments.lock().await.as_mut().unwrap().fetched_at -= chrono::Duration::days(1);
+        let map = client.get_instruments().await.unwrap();
+        assert_eq!(server.requests("t8436").await.len(), 2);
+        assert_eq!(map["005930"].upper_limit, 91_000);
+        assert_eq!(map["005930"].lower_limit, 49_000);
+
+        let err = client.get_instrument("999990").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BrokerError>(),
+            Some(BrokerError::InvalidTicker(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_listing_date() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+        for _ in 0..2 {
+            let samsung = client.get_instrument("005930").await.unwrap();
+            assert_eq!(samsung.listing_date, NaiveDate::from_ymd_opt(1975, 6, 11));
+            assert_eq!(
+                client.get_instrument("092190").await.unwrap().listing_date,
+                None
+            );
+        }
+        // 종목마다 한 번만 조회한다. ELW 는 조회하지 않는다.
+        assert_eq!(server.requests("t1102").await.len(), 2);
+        assert_eq!(
+            client.get_instrument("580001").await.unwrap().listing_date,
+            None
+        );
+        assert_eq!(server.requests("t1102").await.len(), 2);
+    }
+
+    #[test]
+    fn test_instrument_cache() {
+        let at = |day, hour| {
+            NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+        let cache = InstrumentCache {
+            fetched_at: at(16, 9),
+            instruments: Arc::new(HashMap::new()),
+        };
+        assert!(cache.is_fresh(at(16, 20)));
+        assert!(cache.is_fresh(at(17, 7)));
+        assert!(!cache.is_fresh(at(17, 8)));
+    }
+
+    #[test]
+    fn test_is_preferred() {
+        // 보통주, 신규 영문 코드 보통주
+        assert!(!is_preferred("005930", false));
+        assert!(!is_preferred("0001A0", false));
+        // 1우, 2우B, 3우B, 신형 우선주
+        assert!(is_preferred("005935", false));
+        assert!(is_preferred("005387", false));
+        assert!(is_preferred("003549", false));
+        assert!(is_preferred("00104K", false));
+        // 스팩은 끝자리와 상관없이 보통주
+        assert!(!is_preferred("448785", true));
+    }
+
+    #[tokio::test]
+    async fn test_order_isu_no_by_kind() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        for ticker in ["069500", "530031"] {
+            client
+                .order(
+                    ticker,
+                    1,
+                    0,
+                    OrderAction::Buy,
+                    OrderType::Market,
+                    TimeInForce::Day,
+                )
+                .await
+                .unwrap();
+        }
+        let orders = server.requests("CSPAT00601").await;
+        assert_eq!(orders[0]["CSPAT00601InBlock1"]["IsuNo"], "A069500");
+        assert_eq!(orders[1]["CSPAT00601InBlock1"]["IsuNo"], "Q530031");
     }
 
     #[tokio::test]
-    async fn test_get_tickers() {
-        let mut client = LsSecClient::new(KEY.to_string(), SECRET.to_string());
-        let map = client.get_tickers().await;
-        let map = client.get_tickers().await;
-        let map = client.get_tickers().await;
+    async fn test_order_conditions() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        client
+            .order(
+                "005930",
+                1,
+                70000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Ioc,
+            )
+            .await
+            .unwrap();
+        let order = client
+            .order(
+                "005930",
+                1,
+                70000,
+                OrderAction::Sell,
+                OrderType::BestLimit,
+                TimeInForce::Fok,
+            )
+            .await
+            .unwrap();
+        assert_eq!(order.time_in_force, TimeInForce::Fok);
+        let orders = server.requests("CSPAT00601").await;
+        let ioc = &orders[0]["CSPAT00601InBlock1"];
+        assert_eq!(
+            (ioc["OrdprcPtnCode"].as_str(), ioc["OrdCndiTpCode"].as_str()),
+            (Some("00"), Some("1"))
+        );
+        assert_eq!(ioc["OrdPrc"], 70000);
+        let fok = &orders[1]["CSPAT00601InBlock1"];
+        assert_eq!(
+            (fok["OrdprcPtnCode"].as_str(), fok["OrdCndiTpCode"].as_str()),
+            (Some("06"), Some("2"))
+        );
+        assert_eq!(fok["OrdPrc"], 0);
+
+        // 조건부지정가에는 IOC 를 붙일 수 없고, 지정가 계열은 가격이 있어야 한다.
+        for (order_type, time_in_force, price) in [
+            (OrderType::ConditionalLimit, TimeInForce::Ioc, 70000),
+            (OrderType::PriorityLimit, TimeInForce::Fok, 0),
+            (OrderType::AfterHoursSingle, TimeInForce::Day, 0),
+        ] {
+            assert!(client
+                .order(
+                    "005930",
+                    1,
+                    price,
+                    OrderAction::Buy,
+                    order_type,
+                    time_in_force
+                )
+                .await
+                .is_err());
+        }
+        assert_eq!(server.requests("CSPAT00601").await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_order_price_rules() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        // 전일 종가 70,000: 호가 단위 100원, 상한가 91,000
+        for price in [70_050, 91_100, 48_900] {
+            let result = client
+                .order(
+                    "005930",
+                    1,
+                    price,
+                    OrderAction::Buy,
+                    OrderType::Limit,
+                    TimeInForce::Day,
+                )
+                .await;
+            assert!(result.is_err(), "price {}", price);
+        }
+        assert!(server.requests("CSPAT00601").await.is_empty());
+
+        let order = client
+            .order(
+                "005930",
+                1,
+                91_000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        assert!(client.order_modify(order, 1, 70_010).await.is_err());
+        assert!(server.requests("CSPAT00701").await.is_empty());
     }
 
     #[tokio::test]
     async fn test_limit_orders() {
-        let client = LsSecClient::new(KEY.to_string(), SECRET.to_string());
-        tokio::time::sleep(std::time::Duration::from_secs(2));
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
 
         let result = client
-            .order("092190", 1, 4200, OrderAction::Buy, OrderType::Limit)
+            .order(
+                "092190",
+                1,
+                4200,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
             .await
             .expect("error order");
-        tokio::time::sleep(time::Duration::from_secs(2));
         client.order_cancel(result).await.expect("error cancel");
-        tokio::time::sleep(time::Duration::from_secs(2));
+
+        let order = &server.requests("CSPAT00601").await[0]["CSPAT00601InBlock1"];
+        assert_eq!(order["IsuNo"], "A092190");
+        assert_eq!(order["OrdPrc"], 4200);
+        assert_eq!(order["OrdprcPtnCode"], "00");
+        let cancel = &server.requests("CSPAT00801").await[0]["CSPAT00801InBlock1"];
+        assert_eq!(cancel["OrgOrdNo"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_order_rejected() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        server
+            .reject_orders("02714", "주문가능금액이 부족합니다.")
+            .await;
+        let err = client
+            .order(
+                "005930",
+                100,
+                70000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BrokerError>(),
+            Some(BrokerError::InsufficientFunds(_))
+        ));
+
+        let err = client
+            .api_call("/stock/etc", "t9999", &serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BrokerError>(),
+            Some(BrokerError::Rejected { code, .. }) if code == "IGW00000"
+        ));
     }
 
     #[tokio::test]
     async fn test_market_order() {
-        let client = LsSecClient::new(KEY.to_string(), SECRET.to_string());
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
 
         let result = client
-            .order("092190", 1, 0, OrderAction::Buy, OrderType::Market)
+            .order(
+                "092190",
+                1,
+                0,
+                OrderAction::Buy,
+                OrderType::Market,
+                TimeInForce::Day,
+            )
             .await
             .expect("error order");
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
         client.order_cancel(result).await.expect("error cancel");
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
+
+        let order = &server.requests("CSPAT00601").await[0]["CSPAT00601InBlock1"];
+        assert_eq!(order["OrdPrc"], 0);
+        assert_eq!(order["OrdprcPtnCode"], "03");
+        assert_eq!(order["BnsTpCode"], "2");
+    }
+
+    #[tokio::test]
+    async fn test_order_modify() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        let tk = CancellationToken::new();
+        let mut rx = client
+            .connect_websocket_order_transaction(tk.clone())
+            .await
+            .unwrap();
+        server.wait_subscribed("SC2", "").await.unwrap();
+
+        let order = client
+            .order(
+                "005930",
+                1,
+                70000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let amended = client.order_modify(order.clone(), 2, 69900).await.unwrap();
+        assert_eq!(amended.original_id, Some(order.id));
+        assert_eq!(amended.quantity, 2);
+
+        let request = &server.requests("CSPAT00701").await[0]["CSPAT00701InBlock1"];
+        assert_eq!(request["OrgOrdNo"], order.id);
+        assert_eq!(request["OrdPrc"], 69900);
+
+        let _accepted = rx.recv().await.unwrap();
+        let edit = rx.recv().await.unwrap();
+        assert!(matches!(edit.result, OrderResultType::Edit));
+        assert_eq!(edit.id, amended.id.to_string());
+        assert_eq!(edit.original_id, Some(order.id.to_string()));
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_partial_fills() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        let tk = CancellationToken::new();
+        let mut rx = client
+            .connect_websocket_order_transaction(tk.clone())
+            .await
+            .unwrap();
+        server.wait_subscribed("SC1", "").await.unwrap();
+
+        let order = client
+            .order(
+                "005930",
+                10,
+                70000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let accepted = rx.recv().await.unwrap();
+        assert_eq!(accepted.result, OrderResultType::Wait);
+        assert_eq!(accepted.ticker.as_deref(), Some("005930"));
+
+        server.push_execution(order.id, "005930", 4, 70000, 6).await;
+        server.push_execution(order.id, "005930", 6, 69900, 0).await;
+
+        let partial = rx.recv().await.unwrap();
+        assert_eq!(partial.result, OrderResultType::PartiallyFilled);
+        assert_eq!(partial.id, order.id.to_string());
+        assert_eq!(partial.ticker.as_deref(), Some("005930"));
+        let fill = partial.fill.unwrap();
+        assert!(matches!(fill.action, OrderAction::Buy));
+        assert_eq!((fill.quantity, fill.price, fill.remaining), (4, 70000, 6));
+        assert_eq!(fill.time, NaiveTime::from_hms_opt(9, 30, 15).unwrap());
+
+        let filled = rx.recv().await.unwrap();
+        assert_eq!(filled.result, OrderResultType::Success);
+        let fill = filled.fill.unwrap();
+        assert_eq!((fill.quantity, fill.price, fill.remaining), (6, 69900, 0));
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_order_history() {
+        let server = MockLsServer::start().await.unwrap();
+        server.set_page_size(2).await;
+        let client = client(&server).with_scheduler(Scheduler::new(RateLimit::per_second(100)));
+
+        let filled = client
+            .order(
+                "005930",
+                10,
+                70000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let cancelled = client
+            .order(
+                "092190",
+                5,
+                4200,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let resting = client
+            .order(
+                "030520",
+                3,
+                20000,
+                OrderAction::Sell,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        server
+            .push_execution(filled.id, "005930", 4, 70000, 6)
+            .await;
+        client.order_cancel(cancelled.clone()).await.unwrap();
+        let amended = client
+            .order_modify(resting.clone(), 3, 20100)
+            .await
+            .unwrap();
+
+        let orders = client.get_orders(OrderFilter::default()).await.unwrap();
+        let statuses: Vec<_> = orders.iter().map(|o| (o.id, o.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (filled.id, OrderStatus::PartiallyFilled),
+                (cancelled.id, OrderStatus::Cancelled),
+                (resting.id, OrderStatus::Amended),
+                (amended.id, OrderStatus::Accepted),
+            ]
+        );
+        assert_eq!(orders[0].filled_quantity, 4);
+        assert_eq!(orders[0].remaining_quantity, 6);
+        assert_eq!(orders[3].original_id, Some(resting.id));
+        assert_eq!(orders[3].action, OrderAction::Sell);
+
+        let open = client.get_open_orders().await.unwrap();
+        let ids: Vec<_> = open.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![filled.id, amended.id]);
+
+        let today = chrono::Local::now().date_naive();
+        let executions = client.get_executions(today).await.unwrap();
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].symbol, "005930");
+        assert_eq!(executions[0].filled_price, 70000);
+        assert_eq!(executions[0].status, OrderStatus::PartiallyFilled);
+        let yesterday = today.pred_opt().unwrap();
+        assert!(client.get_executions(yesterday).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_candles() {
+        let server = MockLsServer::start().await.unwrap();
+        server.set_page_size(3).await;
+        let client = client(&server).with_scheduler(Scheduler::new(RateLimit::per_second(100)));
+        let from = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 7, 12).unwrap();
+
+        let daily = client
+            .get_candles("005930", CandleInterval::Day, from, to)
+            .await
+            .unwrap();
+        assert_eq!(daily.len(), 10);
+        assert_eq!(daily[0].datetime, from.and_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(daily[9].close, 70000 + 11 * 100);
+        assert_eq!(server.requests("t8410").await.len(), 4);
+
+        let minutes = client
+            .get_candles("005930", CandleInterval::Minute(30), from, from)
+            .await
+            .unwrap();
+        assert_eq!(minutes.len(), 14);
+        assert_eq!(minutes[0].datetime, from.and_hms_opt(9, 0, 0).unwrap());
+        assert_eq!(minutes[13].datetime, from.and_hms_opt(15, 30, 0).unwrap());
+        assert!(minutes.windows(2).all(|w| w[0].datetime < w[1].datetime));
     }
 
     #[tokio::test]
     async fn test_tick() {
-        let client = Arc::new(LsSecClient::new(KEY.to_string(), SECRET.to_string()));
+        let server = MockLsServer::start().await.unwrap();
+        let client = Arc::new(client(&server));
 
-        let result = client.get_tickers().await.unwrap();
         let tk = CancellationToken::new();
-        let mut sockets = client.connect_websocket(tk).await.unwrap();
+        let mut sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        client.subscribe("005930").await.unwrap();
+        server.wait_subscribed("S3_", "005930").await.unwrap();
 
-        let subsclient = client.clone();
-        tokio::spawn(async move { subsclient.subscribe("005930").await.unwrap() });
+        server.push_tick("005930", 70000, 10).await;
+        let tick = sockets.recv().await.unwrap();
+        assert_eq!(tick.ticker, "005930");
+        assert_eq!(tick.price, 70000);
+        tk.cancel();
+    }
 
-        while let Some(tick) = sockets.recv().await {
-            println!("{:?}", tick)
-        }
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let server = MockLsServer::start().await.unwrap();
+        let path = std::env::temp_dir().join(format!("ls-frames-{}.jsonl.gz", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let client = client(&server).with_recorder(FrameRecorder::open(&path).unwrap());
+
+        let tk = CancellationToken::new();
+        let mut sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        client.subscribe("005930").await.unwrap();
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+        server.push_tick("005930", 70000, 10).await;
+        sockets.recv().await.unwrap();
+        tk.cancel();
+
+        // 기록은 별도 스레드에서 묶어서 하므로 파일에 나타날 때까지 기다린다.
+        let frames = tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                let frames: Vec<_> = read_frames(&path)
+                    .map(|frames| frames.filter_map(Result::ok).collect())
+                    .unwrap_or_default();
+                if frames.iter().any(|f| f.frame.contains("\"price\"")) {
+                    break frames;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(frames.iter().all(|f| f.stream == StreamKind::Tick));
+
+        let replay = ReplayBroker::new(frames).with_speed(ReplaySpeed::Unlimited);
+        replay.subscribe("005930").await.unwrap();
+        let mut ticks = replay
+            .connect_websocket(CancellationToken::new())
+            .await
+            .unwrap();
+        let tick = ticks.recv().await.unwrap();
+        assert_eq!((tick.ticker.as_str(), tick.price), ("005930", 70000));
+        assert!(ticks.recv().await.is_none());
     }
 
     #[tokio::test]
-    async fn test_sample() {
-        let client = Arc::new(LsSecClient::new(KEY.to_string(), SECRET.to_string()));
+    async fn test_session_events() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+        let mut sessions = client.session_events();
+
+        let tk = CancellationToken::new();
+        let mut sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        server.wait_subscribed("JIF", "0").await.unwrap();
+        client.subscribe("005930").await.unwrap();
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+
+        server
+            .push(
+                "JIF",
+                "0",
+                serde_json::json!({"jangubun": "1", "jstatus": "21"}),
+            )
+            .await;
+        server.push_tick("005930", 70000, 10).await;
+
+        let event = sessions.recv().await.unwrap();
+        assert_eq!(event.market, Market::KOSPI);
+        assert_eq!(event.phase, SessionPhase::Continuous);
+        // 장운영정보는 체결로 나가지 않는다.
+        assert_eq!(sockets.recv().await.unwrap().price, 70000);
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_tradability_events() {
+        let server = MockLsServer::start().await.unwrap();
+        server.set_halted(&["030520"]).await;
+        let client = client(&server);
+        let mut events = client.tradability_events();
 
         let tk = CancellationToken::new();
-        if let Ok(mut rx) = client.connect_websocket_order_transaction(tk).await {
-            while let Some(i)  = rx.recv().await {
-                println!("{:?}",i)
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        server.wait_subscribed("VI_", "000000").await.unwrap();
+        server
+            .push(
+                "VI_",
+                "000000",
+                serde_json::json!({"vi_gubun": "2", "vi_trgprice": "77000", "shcode": "005930"}),
+            )
+            .await;
+
+        let mut received = HashMap::new();
+        for _ in 0..2 {
+            let event = events.recv().await.unwrap();
+            received.insert(event.ticker, event.tradability);
+        }
+        assert_eq!(received["030520"], Tradability::Halted);
+        assert_eq!(client.tradability("030520"), Tradability::Halted);
+        assert_eq!(
+            client.tradability("005930"),
+            Tradability::Vi {
+                kind: crate::broker::tradability::ViKind::Dynamic,
+                trigger_price: 77000
             }
+        );
+        assert_eq!(client.tradability("092190"), Tradability::Normal);
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+
+        let tk = CancellationToken::new();
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        client.subscribe("005930").await.unwrap();
+        assert!(client.subscribe("005930").await.is_err());
+        assert_eq!(client.subscriptions().await, vec!["005930".to_string()]);
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+
+        client.unsubscribe("005930").await.unwrap();
+        server.wait_unsubscribed("S3_", "005930").await.unwrap();
+        assert!(client.subscriptions().await.is_empty());
+        assert!(client.unsubscribe("005930").await.is_err());
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_order_book() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server);
+        let mut books = client.order_book_stream();
+
+        let tk = CancellationToken::new();
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        client.subscribe_order_book("092190").await.unwrap();
+        server.wait_subscribed("HA_", "092190").await.unwrap();
+
+        let mut body = serde_json::json!({
+            "hotime": "093001", "shcode": "092190",
+            "totofferrem": "3000", "totbidrem": "1000"
+        });
+        for level in 1..=10 {
+            body[format!("offerho{}", level)] = (4200 + level * 5).to_string().into();
+            body[format!("bidho{}", level)] = (4200 - (level - 1) * 5).to_string().into();
+            body[format!("offerrem{}", level)] = "300".into();
+            body[format!("bidrem{}", level)] = "100".into();
         }
+        server.push("HA_", "092190", body).await;
+
+        let book = books.recv().await.unwrap();
+        assert_eq!(book.ticker, "092190");
+        assert_eq!(book.asks.len(), 10);
+        assert_eq!(book.best_ask().unwrap().price, 4205);
+        assert_eq!(book.best_bid().unwrap().price, 4200);
+        assert_eq!(book.spread(), Some(5));
+        assert_eq!(book.imbalance(), Some(-0.5));
+
+        client.unsubscribe_order_book("092190").await.unwrap();
+        server.wait_unsubscribed("HA_", "092190").await.unwrap();
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resubscribes() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server).with_reconnect_policy(ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(100),
+            stale_timeout: None,
+        });
+        let mut events = client.connection_events();
+
+        let tk = CancellationToken::new();
+        let mut sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        client.subscribe("005930").await.unwrap();
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+        assert_eq!(
+            events.recv().await.unwrap().state,
+            ConnectionState::Connected
+        );
+
+        server.disconnect_all().await;
+        assert_eq!(
+            events.recv().await.unwrap().state,
+            ConnectionState::Disconnected
+        );
+        assert_eq!(
+            events.recv().await.unwrap().state,
+            ConnectionState::Reconnected
+        );
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+
+        server.push_tick("005930", 70100, 5).await;
+        let tick = sockets.recv().await.unwrap();
+        assert_eq!(tick.price, 70100);
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_stale_feed_reconnects() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = client(&server).with_reconnect_policy(ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(100),
+            stale_timeout: Some(Duration::from_millis(100)),
+        });
+        let mut events = client.connection_events();
+
+        let tk = CancellationToken::new();
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        let states: Vec<_> = [
+            events.recv().await.unwrap(),
+            events.recv().await.unwrap(),
+            events.recv().await.unwrap(),
+        ]
+        .iter()
+        .map(|e| e.state)
+        .collect();
+        assert_eq!(
+            states,
+            vec![
+                ConnectionState::Connected,
+                ConnectionState::Disconnected,
+                ConnectionState::Reconnected
+            ]
+        );
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_sample() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = Arc::new(client(&server));
+
+        let tk = CancellationToken::new();
+        let mut rx = client
+            .connect_websocket_order_transaction(tk.clone())
+            .await
+            .unwrap();
+        server.wait_subscribed("SC0", "").await.unwrap();
+
+        let order = client
+            .order(
+                "005930",
+                1,
+                70000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let result = rx.recv().await.unwrap();
+        assert_eq!(result.id, order.id.to_string());
+        assert!(matches!(result.result, OrderResultType::Wait));
+        tk.cancel();
     }
 }
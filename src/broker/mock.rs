@@ -0,0 +1,912 @@
+//! 통합 테스트용 LS증권 OpenAPI 대역 서버.
+//! `LsSecClient` 가 사용하는 REST TR 과 웹소켓 실시간 TR 을 로컬에서 흉내낸다.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_util::sync::CancellationToken;
+
+use crate::broker::lssec::Environment;
+
+const MOCK_TOKEN_PREFIX: &str = "mock-access-token";
+
+struct MockClient {
+    tx: UnboundedSender<Message>,
+    subscriptions: HashSet<(String, String)>,
+}
+
+struct MockOrder {
+    ord_no: i64,
+    org_ord_no: i64,
+    ticker: String,
+    // "1" 매도, "2" 매수
+    action: String,
+    order_type: String,
+    quantity: i64,
+    price: i64,
+    filled: i64,
+    filled_amount: i64,
+    status: &'static str,
+}
+
+impl MockOrder {
+    fn average_price(&self) -> i64 {
+        if self.filled > 0 {
+            self.filled_amount / self.filled
+        } else {
+            0
+        }
+    }
+
+    fn remaining(&self) -> i64 {
+        self.quantity - self.filled
+    }
+
+    // t0425OutBlock1
+    fn t0425(&self) -> Value {
+        json!({
+            "ordno": self.ord_no,
+            "orgordno": self.org_ord_no,
+            "expcode": self.ticker,
+            "medosu": if self.action == "1" { "매도" } else { "매수" },
+            "qty": self.quantity,
+            "price": self.price,
+            "cheqty": self.filled,
+            "cheprice": self.average_price(),
+            "ordrem": self.remaining(),
+            "status": self.status,
+            "hogagb": self.order_type
+        })
+    }
+
+    // CSPAQ13700OutBlock3
+    fn cspaq13700(&self) -> Value {
+        json!({
+            "OrdDt": chrono::Local::now().format("%Y%m%d").to_string(),
+            "OrdNo": self.ord_no,
+            "OrgOrdNo": self.org_ord_no,
+            "IsuNo": format!("A{}", self.ticker),
+            "BnsTpCode": self.action,
+            "OrdprcPtnCode": self.order_type,
+            "OrdQty": self.quantity,
+            "OrdPrc": self.price,
+            "ExecQty": self.filled,
+            "ExecPrc": self.average_price(),
+            "CancCnfQty": if self.status == "취소확인" { self.remaining() } else { 0 },
+            "RjtQty": 0
+        })
+    }
+}
+
+struct MockState {
+    tickers: Vec<Value>,
+    // t1405 매매정지 종목
+    halted: Vec<String>,
+    balance: i64,
+    positions: Vec<Value>,
+    page_size: usize,
+    next_order_no: i64,
+    orders: Vec<MockOrder>,
+    // 설정되면 신규 주문을 이 rsp_cd/rsp_msg 로 거부한다.
+    order_error: Option<(String, String)>,
+    // 설정되면 신규 주문에 500 으로 응답한다. true 면 주문은 접수해 둔다.
+    order_lost: Option<bool>,
+    requests: Vec<(String, Value)>,
+    issued_tokens: Vec<String>,
+    valid_tokens: HashSet<String>,
+    revoked_tokens: Vec<String>,
+    token_lifetime: i64,
+    clients: HashMap<usize, MockClient>,
+    next_client_id: usize,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            tickers: vec![
+                json!({"hname": "삼성전자", "shcode": "005930", "expcode": "KR7005930003", "etfgubun": "0", "memedan": "1", "jnilclose": 70000, "uplmtprice": "91000", "dnlmtprice": "49000", "gubun": "1"}),
+                json!({"hname": "삼성전자우", "shcode": "005935", "expcode": "KR7005931001", "etfgubun": "0", "memedan": "1", "jnilclose": 57000, "gubun": "1"}),
+                json!({"hname": "서울바이오시스", "shcode": "092190", "expcode": "KR7092190008", "etfgubun": "0", "memedan": "1", "jnilclose": 4200, "gubun": "2"}),
+                json!({"hname": "한글과컴퓨터", "shcode": "030520", "expcode": "KR7030520001", "etfgubun": "0", "memedan": "1", "jnilclose": 20000, "gubun": "2"}),
+                json!({"hname": "KODEX 200", "shcode": "069500", "expcode": "KR7069500007", "etfgubun": "1", "memedan": "1", "jnilclose": 35000, "gubun": "1"}),
+                json!({"hname": "삼성 레버리지 WTI원유 선물 ETN", "shcode": "530031", "expcode": "KRG530000315", "etfgubun": "2", "memedan": "1", "jnilclose": 10000, "gubun": "1"}),
+                json!({"hname": "코넥스 상장사", "shcode": "216400", "expcode": "KR7216400000", "etfgubun": "0", "memedan": "1", "jnilclose": 5000, "gubun": "3"}),
+                json!({"hname": "알 수 없는 시장", "shcode": "999990", "expcode": "", "etfgubun": "0", "memedan": "1", "jnilclose": 1000, "gubun": "9"}),
+            ],
+            halted: Vec::new(),
+            balance: 1_000_000,
+            positions: vec![json!({
+                "expcode": "030520",
+                "janqty": 10,
+                "appamt": 200000.0,
+                "pamt": 19000.0,
+                "dtsunik": 10000.0,
+                "sunikrt": "5.26",
+                "fee": 0.0,
+                "tax": 0.0
+            })],
+            page_size: 20,
+            next_order_no: 1,
+            orders: Vec::new(),
+            order_error: None,
+            order_lost: None,
+            requests: Vec::new(),
+            issued_tokens: Vec::new(),
+            valid_tokens: HashSet::new(),
+            revoked_tokens: Vec::new(),
+            token_lifetime: 86400,
+            clients: HashMap::new(),
+            next_client_id: 0,
+        }
+    }
+}
+
+impl MockState {
+    fn push(&self, tr_cd: &str, tr_key: &str, body: Value) {
+        let frame = json!({
+            "header": {"tr_cd": tr_cd, "tr_key": tr_key},
+            "body": body
+        })
+        .to_string();
+        for client in self.clients.values() {
+            if client
+                .subscriptions
+                .contains(&(tr_cd.to_string(), tr_key.to_string()))
+            {
+                let _ = client.tx.send(Message::text(frame.clone()));
+            }
+        }
+    }
+}
+
+/// 로컬 포트에 HTTP 서버와 웹소켓 서버를 띄운다. drop 되면 함께 종료된다.
+pub struct MockLsServer {
+    api_url: String,
+    ws_url: String,
+    state: Arc<Mutex<MockState>>,
+    cancel: CancellationToken,
+}
+
+impl MockLsServer {
+    pub async fn start() -> Result<Self> {
+        let http = TcpListener::bind("127.0.0.1:0").await?;
+        let ws = TcpListener::bind("127.0.0.1:0").await?;
+        let api_url = format!("http://{}", http.local_addr()?);
+        let ws_url = format!("ws://{}/websocket", ws.local_addr()?);
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let cancel = CancellationToken::new();
+
+        let http_state = Arc::clone(&state);
+        let http_cancel = cancel.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    accepted = http.accept() => {
+                        if let Ok((stream, _)) = accepted {
+                            tokio::spawn(handle_http(stream, Arc::clone(&http_state)));
+                        }
+                    }
+                    _ = http_cancel.cancelled() => break,
+                }
+            }
+        });
+
+        let ws_state = Arc::clone(&state);
+        let ws_cancel = cancel.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    accepted = ws.accept() => {
+                        if let Ok((stream, _)) = accepted {
+                            tokio::spawn(handle_ws(stream, Arc::clone(&ws_state), ws_cancel.clone()));
+                        }
+                    }
+                    _ = ws_cancel.cancelled() => break,
+                }
+            }
+        });
+
+        Ok(Self {
+            api_url,
+            ws_url,
+            state,
+            cancel,
+        })
+    }
+
+    pub fn environment(&self) -> Environment {
+        Environment::Custom {
+            api_url: self.api_url.clone(),
+            ws_url: self.ws_url.clone(),
+        }
+    }
+
+    /// 수신한 REST 요청 본문 중 `tr_cd` 에 해당하는 것
+    pub async fn requests(&self, tr_cd: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .await
+            .requests
+            .iter()
+            .filter(|(cd, _)| cd == tr_cd)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+
+    /// 지금까지 발급한 접근 토큰
+    pub async fn issued_tokens(&self) -> Vec<String> {
+        self.state.lock().await.issued_tokens.clone()
+    }
+
+    /// 폐기 요청을 받은 접근 토큰
+    pub async fn revoked_tokens(&self) -> Vec<String> {
+        self.state.lock().await.revoked_tokens.clone()
+    }
+
+    /// 잔고 종목을 추가한다.
+    pub async fn add_position(&self, ticker: &str, quantity: i64) {
+        self.state.lock().await.positions.push(json!({
+            "expcode": ticker,
+            "janqty": quantity,
+            "appamt": 0.0,
+            "pamt": 0.0,
+            "dtsunik": 0.0,
+            "sunikrt": "0.00",
+            "fee": 0.0,
+            "tax": 0.0
+        }));
+    }
+
+    /// 이후 신규 주문(CSPAT00601)을 주어진 응답 코드로 거부한다.
+    pub async fn reject_orders(&self, code: &str, message: &str) {
+        self.state.lock().await.order_error = Some((code.to_string(), message.to_string()));
+    }
+
+    /// 이후 신규 주문(CSPAT00601)에 500 으로 응답한다. `placed` 면 응답만 잃고 주문은 접수된다.
+    pub async fn lose_orders(&self, placed: bool) {
+        self.state.lock().await.order_lost = Some(placed);
+    }
+
+    /// t1405 로 돌려줄 매매정지 종목
+    pub async fn set_halted(&self, tickers: &[&str]) {
+        self.state.lock().await.halted = tickers.iter().map(|t| t.to_string()).collect();
+    }
+
+    /// 연속조회 TR 한 페이지에 담을 건수
+    pub async fn set_page_size(&self, size: usize) {
+        self.state.lock().await.page_size = size;
+    }
+
+    /// 새로 발급하는 토큰의 `expires_in` (초)
+    pub async fn set_token_lifetime(&self, secs: i64) {
+        self.state.lock().await.token_lifetime = secs;
+    }
+
+    /// 발급한 토큰을 모두 만료시킨다. 이후 요청은 IGW00121 로 거절된다.
+    pub async fn expire_tokens(&self) {
+        self.state.lock().await.valid_tokens.clear();
+    }
+
+    /// 접속 중인 모든 클라이언트의 실시간 등록 목록
+    pub async fn subscriptions(&self) -> HashSet<(String, String)> {
+        self.state
+            .lock()
+            .await
+            .clients
+            .values()
+            .flat_map(|c| c.subscriptions.iter().cloned())
+            .collect()
+    }
+
+    pub async fn wait_subscribed(&self, tr_cd: &str, tr_key: &str) -> Result<()> {
+        let key = (tr_cd.to_string(), tr_key.to_string());
+        for _ in 0..200 {
+            if self.subscriptions().await.contains(&key) {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Err(anyhow!("{} {} was never subscribed", tr_cd, tr_key))
+    }
+
+    pub async fn wait_unsubscribed(&self, tr_cd: &str, tr_key: &str) -> Result<()> {
+        let key = (tr_cd.to_string(), tr_key.to_string());
+        for _ in 0..200 {
+            if !self.subscriptions().await.contains(&key) {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Err(anyhow!("{} {} was never unsubscribed", tr_cd, tr_key))
+    }
+
+    /// 접속 중인 웹소켓을 모두 끊는다.
+    pub async fn disconnect_all(&self) {
+        let mut state = self.state.lock().await;
+        for (_, client) in state.clients.drain() {
+            let _ = client.tx.send(Message::Close(None));
+        }
+    }
+
+    /// 실시간 TR 프레임을 등록한 클라이언트에게 보낸다.
+    pub async fn push(&self, tr_cd: &str, tr_key: &str, body: Value) {
+        self.state.lock().await.push(tr_cd, tr_key, body);
+    }
+
+    /// 주문 체결(SC1)을 보낸다. `remaining` 이 0 보다 크면 부분 체결이다.
+    pub async fn push_execution(
+        &self,
+        ord_no: i64,
+        ticker: &str,
+        quantity: i64,
+        price: i64,
+        remaining: i64,
+    ) {
+        if let Some(order) = self
+            .state
+            .lock()
+            .await
+            .orders
+            .iter_mut()
+            .find(|o| o.ord_no == ord_no)
+        {
+            order.filled += quantity;
+            order.filled_amount += quantity * price;
+        }
+        self.push(
+            "SC1",
+            "",
+            json!({
+                "ordno": format!("{:010}", ord_no),
+                "shtnIsuno": format!("A{}", ticker),
+                "bnstp": "2",
+                "execqty": quantity.to_string(),
+                "execprc": price.to_string(),
+                "exectime": "093015123",
+                "unercqty": remaining.to_string()
+            }),
+        )
+        .await;
+    }
+
+    pub async fn push_tick(&self, ticker: &str, price: i64, volume: i64) {
+        let tr_cd = {
+            let state = self.state.lock().await;
+            let kosdaq = state
+                .tickers
+                .iter()
+                .any(|t| t["shcode"] == ticker && t["gubun"] == "2");
+            if kosdaq {
+                "K3_"
+            } else {
+                "S3_"
+            }
+        };
+        self.push(
+            tr_cd,
+            ticker,
+            json!({
+                "chetime": "093000",
+                "sign": "2",
+                "change": "100",
+                "price": price.to_string(),
+                "open": price.to_string(),
+                "high": price.to_string(),
+                "low": price.to_string(),
+                "cgubun": "+",
+                "cvolume": volume.to_string(),
+                "volume": volume.to_string(),
+                "shcode": ticker
+            }),
+        )
+        .await;
+    }
+}
+
+impl Drop for MockLsServer {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4)
+}
+
+async fn handle_http(mut stream: TcpStream, state: Arc<Mutex<MockState>>) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = find_header_end(&buf) {
+            break end;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let path = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .context("invalid request line")?
+        .to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+    let length: usize = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = &buf[header_end..(header_end + length).min(buf.len())];
+
+    let (status, mut response) = route(&path, &headers, body, &state).await;
+    // 라우트가 정한 연속조회 키가 없으면 OutBlock 에 다음 페이지 cts 가 있을 때 붙인다.
+    let tr_cont_key = match response.as_object_mut().and_then(|r| r.remove(TR_CONT_KEY)) {
+        Some(key) => key.as_str().map(|v| v.to_string()),
+        None => response
+            .as_object()
+            .into_iter()
+            .flat_map(|blocks| blocks.values())
+            .filter_map(|block| block.as_object())
+            .flat_map(|block| block.iter())
+            .find(|(field, value)| {
+                field.starts_with("cts_") && value.as_str().is_some_and(|v| !v.is_empty())
+            })
+            .and_then(|(_, value)| value.as_str().map(|v| v.to_string())),
+    };
+    let tr_cont = match tr_cont_key {
+        Some(key) => format!("tr_cont: Y\r\ntr_cont_key: {}\r\n", key),
+        None => "tr_cont: N\r\n".to_string(),
+    };
+    let response = response.to_string();
+    let reply = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json; charset=utf-8\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        tr_cont,
+        response.len(),
+        response
+    );
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+// 실제 서버처럼 마지막 페이지에도 cts 를 채워 주는 TR 은 이 키로 연속 여부를 따로 정한다.
+const TR_CONT_KEY: &str = "tr_cont_key";
+
+fn ok_response(mut body: Value) -> (&'static str, Value) {
+    body["rsp_cd"] = json!("00000");
+    body["rsp_msg"] = json!("정상적으로 처리되었습니다.");
+    ("200 OK", body)
+}
+
+async fn route(
+    path: &str,
+    headers: &HashMap<String, String>,
+    body: &[u8],
+    state: &Mutex<MockState>,
+) -> (&'static str, Value) {
+    if path == "/oauth2/token" {
+        let mut state = state.lock().await;
+        let token = format!("{}-{}", MOCK_TOKEN_PREFIX, state.issued_tokens.len() + 1);
+        state.issued_tokens.push(token.clone());
+        state.valid_tokens.insert(token.clone());
+        return (
+            "200 OK",
+            json!({
+                "access_token": token,
+                "scope": "oob",
+                "token_type": "Bearer",
+                "expires_in": state.token_lifetime
+            }),
+        );
+    }
+    if path == "/oauth2/revoke" {
+        let form = String::from_utf8_lossy(body);
+        let token = form
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+            .unwrap_or_default()
+            .to_string();
+        let mut state = state.lock().await;
+        state.valid_tokens.remove(&token);
+        state.revoked_tokens.push(token);
+        return ("200 OK", json!({ "code": "200", "message": "revoked" }));
+    }
+
+    let tr_cd = headers.get("tr_cd").cloned().unwrap_or_default();
+    let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+    let mut state = state.lock().await;
+    state.requests.push((tr_cd.clone(), body.clone()));
+
+    let bearer = headers
+        .get("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !state.valid_tokens.contains(bearer) {
+        return (
+            "401 Unauthorized",
+            json!({ "rsp_cd": "IGW00121", "rsp_msg": "유효하지 않은 token 입니다." }),
+        );
+    }
+
+    match tr_cd.as_str() {
+        "t8436" => ok_response(json!({ "t8436OutBlock": state.tickers })),
+        "t8431" => ok_response(json!({
+            "t8431OutBlock": [
+                {"hname": "KB E123 삼성전자 콜", "shcode": "580001", "expcode": "KRA580001000", "jnilclose": "55"}
+            ]
+        })),
+        "t1102" => {
+            let listdate = match body["t1102InBlock"]["shcode"].as_str() {
+                Some("005930") => "19750611",
+                _ => "",
+            };
+            ok_response(json!({ "t1102OutBlock": { "listdate": listdate } }))
+        }
+        "t1405" => {
+            let rows: Vec<Value> = match body["t1405InBlock"]["jongchk"].as_str() {
+                Some("2") => state
+                    .halted
+                    .iter()
+                    .map(|ticker| json!({ "shcode": ticker, "hname": "", "price": 0 }))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            ok_response(json!({
+                "t1405OutBlock": { "cts_shcode": "" },
+                "t1405OutBlock1": rows
+            }))
+        }
+        "CSPAQ12200" => ok_response(json!({
+            "CSPAQ12200OutBlock2": { "MnyOrdAbleAmt": state.balance }
+        })),
+        "t0424" => {
+            let cts = body["t0424InBlock"]["cts_expcode"].as_str().unwrap_or("");
+            let start = state
+                .positions
+                .iter()
+                .position(|p| p["expcode"] == cts)
+                .unwrap_or(0);
+            let end = (start + state.page_size).min(state.positions.len());
+            let next = state
+                .positions
+                .get(end)
+                .map(|p| p["expcode"].clone())
+                .unwrap_or(json!(""));
+            ok_response(json!({
+                "t0424OutBlock": { "cts_expcode": next },
+                "t0424OutBlock1": state.positions[start..end]
+            }))
+        }
+        "CSPAT00601" => {
+            if let Some((code, message)) = &state.order_error {
+                return ("200 OK", json!({ "rsp_cd": code, "rsp_msg": message }));
+            }
+            if state.order_lost == Some(false) {
+                return ("500 Internal Server Error", json!({}));
+            }
+            let ord_no = state.next_order_no;
+            state.next_order_no += 1;
+            let block = &body["CSPAT00601InBlock1"];
+            let ticker = block["IsuNo"]
+                .as_str()
+                .unwrap_or("")
+                .trim_start_matches(['A', 'Q', 'J']);
+            state.orders.push(MockOrder {
+                ord_no,
+                org_ord_no: 0,
+                ticker: ticker.to_string(),
+                action: block["BnsTpCode"].as_str().unwrap_or("2").to_string(),
+                order_type: block["OrdprcPtnCode"].as_str().unwrap_or("00").to_string(),
+                quantity: block["OrdQty"].as_i64().unwrap_or(0),
+                price: block["OrdPrc"].as_i64().unwrap_or(0),
+                filled: 0,
+                filled_amount: 0,
+                status: "접수",
+            });
+            state.push(
+                "SC0",
+                "",
+                json!({
+                    "ordno": ord_no.to_string(),
+                    "shtcode": ticker,
+                    "ordqty": block["OrdQty"].to_string(),
+                    "ordprice": block["OrdPrc"].to_string()
+                }),
+            );
+            if state.order_lost == Some(true) {
+                return ("500 Internal Server Error", json!({}));
+            }
+            ok_response(json!({
+                "CSPAT00601OutBlock1": block,
+                "CSPAT00601OutBlock2": { "OrdNo": ord_no }
+            }))
+        }
+        "CSPAT00701" => {
+            let ord_no = state.next_order_no;
+            state.next_order_no += 1;
+            let block = &body["CSPAT00701InBlock1"];
+            let org_ord_no = block["OrgOrdNo"].as_i64().unwrap_or(0);
+            if let Some(index) = state.orders.iter().position(|o| o.ord_no == org_ord_no) {
+                state.orders[index].status = "정정확인";
+                let original = &state.orders[index];
+                let amended = MockOrder {
+                    ord_no,
+                    org_ord_no,
+                    ticker: original.ticker.clone(),
+                    action: original.action.clone(),
+                    order_type: original.order_type.clone(),
+                    quantity: block["OrdQty"].as_i64().unwrap_or(0),
+                    price: block["OrdPrc"].as_i64().unwrap_or(0),
+                    filled: 0,
+                    filled_amount: 0,
+                    status: "접수",
+                };
+                state.orders.push(amended);
+            }
+            state.push(
+                "SC2",
+                "",
+                json!({
+                    "ordno": ord_no.to_string(),
+                    "orgordno": block["OrgOrdNo"].to_string(),
+                    "ordqty": block["OrdQty"].to_string(),
+                    "ordprice": block["OrdPrc"].to_string()
+                }),
+            );
+            ok_response(json!({
+                "CSPAT00701OutBlock1": block,
+                "CSPAT00701OutBlock2": { "OrdNo": ord_no, "PrntOrdNo": block["OrgOrdNo"] }
+            }))
+        }
+        "CSPAT00801" => {
+            let ord_no = state.next_order_no;
+            state.next_order_no += 1;
+            let block = &body["CSPAT00801InBlock1"];
+            let org_ord_no = block["OrgOrdNo"].as_i64().unwrap_or(0);
+            if let Some(order) = state.orders.iter_mut().find(|o| o.ord_no == org_ord_no) {
+                order.status = "취소확인";
+            }
+            state.push(
+                "SC3",
+                "",
+                json!({
+                    "ordno": ord_no.to_string(),
+                    "orgordno": block["OrgOrdNo"].to_string()
+                }),
+            );
+            ok_response(json!({
+                "CSPAT00801OutBlock1": block,
+                "CSPAT00801OutBlock2": { "OrdNo": ord_no, "PrntOrdNo": block["OrgOrdNo"] }
+            }))
+        }
+        "t8410" | "t8412" => {
+            let block = &body[format!("{}InBlock", tr_cd)];
+            let field = |name: &str| block[name].as_str().unwrap_or("").to_string();
+            let parse = |date: String| NaiveDate::parse_from_str(&date, "%Y%m%d").ok();
+            let (Some(from), Some(to)) = (parse(field("sdate")), parse(field("edate"))) else {
+                return (
+                    "200 OK",
+                    json!({ "rsp_cd": "01001", "rsp_msg": "invalid date" }),
+                );
+            };
+            let minutes = block["ncnt"].as_i64().unwrap_or(0);
+            let cts = (field("cts_date"), field("cts_time"));
+
+            let rows: Vec<Value> = mock_candles(from, to, minutes)
+                .into_iter()
+                .filter(|(date, time, _)| {
+                    cts.0.is_empty() || (date.clone(), time.clone()) < cts.clone()
+                })
+                .map(|(_, _, row)| row)
+                .collect();
+            // 최근 봉부터 page_size 건씩
+            let start = rows.len().saturating_sub(state.page_size);
+            let page = rows[start..].to_vec();
+            let next = match page.first() {
+                Some(first) => (first["date"].clone(), first["time"].clone()),
+                None => (json!(""), json!("")),
+            };
+            let mut out = json!({ "cts_date": next.0 });
+            if tr_cd == "t8412" {
+                out["cts_time"] = next.1;
+            }
+            let mut response = json!({});
+            response[TR_CONT_KEY] = if start > 0 {
+                json!(start.to_string())
+            } else {
+                Value::Null
+            };
+            response[format!("{}OutBlock", tr_cd)] = out;
+            response[format!("{}OutBlock1", tr_cd)] = json!(page);
+            ok_response(response)
+        }
+        "t0425" => {
+            let block = &body["t0425InBlock"];
+            let field = |name: &str| block[name].as_str().unwrap_or("").to_string();
+            let (ticker, chegb, medosu) = (field("expcode"), field("chegb"), field("medosu"));
+            let cts = field("cts_ordno").parse::<i64>().unwrap_or(0);
+            let rows: Vec<&MockOrder> = state
+                .orders
+                .iter()
+                .filter(|o| ticker.is_empty() || o.ticker == ticker)
+                .filter(|o| medosu.is_empty() || medosu == "0" || o.action == medosu)
+                .filter(|o| match chegb.as_str() {
+                    "1" => o.filled > 0,
+                    "2" => o.status == "접수" && o.remaining() > 0,
+                    _ => true,
+                })
+                .filter(|o| o.ord_no >= cts)
+                .collect();
+            let page: Vec<Value> = rows
+                .iter()
+                .take(state.page_size)
+                .map(|o| o.t0425())
+                .collect();
+            // 마지막 페이지면 마지막 주문번호가 cts 로 온다.
+            let (next, tr_cont_key) = match rows.get(state.page_size) {
+                Some(o) => (o.ord_no.to_string(), json!(o.ord_no.to_string())),
+                None => (
+                    rows.last()
+                        .map(|o| o.ord_no.to_string())
+                        .unwrap_or_default(),
+                    Value::Null,
+                ),
+            };
+            ok_response(json!({
+                "t0425OutBlock": { "cts_ordno": next },
+                "t0425OutBlock1": page,
+                TR_CONT_KEY: tr_cont_key
+            }))
+        }
+        "CSPAQ13700" => {
+            let date = body["CSPAQ13700InBlock1"]["OrdDt"].as_str().unwrap_or("");
+            let today = chrono::Local::now().format("%Y%m%d").to_string();
+            let rows: Vec<Value> = state
+                .orders
+                .iter()
+                .filter(|o| date == today && o.filled > 0)
+                .map(|o| o.cspaq13700())
+                .collect();
+            ok_response(json!({ "CSPAQ13700OutBlock3": rows }))
+        }
+        _ => (
+            "404 Not Found",
+            json!({ "rsp_cd": "IGW00000", "rsp_msg": format!("unknown tr_cd {}", tr_cd) }),
+        ),
+    }
+}
+
+// 평일마다 일봉(minutes == 0) 또는 09:00~15:30 분봉을 만든다. 종가는 날짜마다 100원씩 오른다.
+fn mock_candles(from: NaiveDate, to: NaiveDate, minutes: i64) -> Vec<(String, String, Value)> {
+    let mut candles = Vec::new();
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        if date.weekday().number_from_monday() > 5 {
+            continue;
+        }
+        let close = 70000 + (date - from).num_days() * 100;
+        let times: Vec<String> = if minutes == 0 {
+            vec![String::new()]
+        } else {
+            (0..=390 / minutes)
+                .map(|i| {
+                    let at = NaiveTime::from_hms_opt(9, 0, 0).unwrap()
+                        + chrono::Duration::minutes(i * minutes);
+                    at.format("%H%M%S").to_string()
+                })
+                .collect()
+        };
+        for time in times {
+            let day = date.format("%Y%m%d").to_string();
+            let mut row = json!({
+                "date": day,
+                "open": close - 50,
+                "high": close + 100,
+                "low": close - 100,
+                "close": close,
+                "jdiff_vol": 1000
+            });
+            if minutes > 0 {
+                row["time"] = json!(time);
+            }
+            candles.push((day, time, row));
+        }
+    }
+    candles
+}
+
+async fn handle_ws(
+    stream: TcpStream,
+    state: Arc<Mutex<MockState>>,
+    cancel: CancellationToken,
+) -> Result<()> {
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (mut write, mut read) = ws.split();
+    let (tx, mut rx) = unbounded_channel::<Message>();
+
+    let id = {
+        let mut state = state.lock().await;
+        let id = state.next_client_id;
+        state.next_client_id += 1;
+        state.clients.insert(
+            id,
+            MockClient {
+                tx,
+                subscriptions: HashSet::new(),
+            },
+        );
+        id
+    };
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let close = matches!(message, Message::Close(_));
+            if write.send(message).await.is_err() || close {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let message = tokio::select! {
+            message = read.next() => message,
+            _ = cancel.cancelled() => None,
+        };
+        let Some(Ok(message)) = message else {
+            break;
+        };
+        let Ok(json) = serde_json::from_str::<Value>(&message.to_string()) else {
+            continue;
+        };
+        let tr_type = json["header"]["tr_type"].as_str().unwrap_or_default();
+        let key = (
+            json["body"]["tr_cd"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            json["body"]["tr_key"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        );
+        let mut state = state.lock().await;
+        if let Some(client) = state.clients.get_mut(&id) {
+            match tr_type {
+                "1" | "3" => {
+                    client.subscriptions.insert(key);
+                }
+                "2" | "4" => {
+                    client.subscriptions.remove(&key);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    state.lock().await.clients.remove(&id);
+    writer.abort();
+    Ok(())
+}
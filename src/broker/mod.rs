@@ -1,18 +1,33 @@
+pub mod calendar;
+pub mod error;
 pub mod lssec;
+#[cfg(test)]
+pub mod mock;
+pub mod paper;
+pub mod price_rules;
+pub mod recorder;
+pub mod replay;
+pub mod scheduler;
+pub mod session;
+pub mod subscription;
+pub mod tradability;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Receiver;
 use tokio_util::sync::CancellationToken;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Market {
     KOSPI,
     KOSDAQ,
+    KONEX,
 }
 
 impl Display for Market {
@@ -20,6 +35,7 @@ impl Display for Market {
         match self {
             Market::KOSPI => write!(f, "KOSPI"),
             Market::KOSDAQ => write!(f, "KOSDAQ"),
+            Market::KONEX => write!(f, "KONEX"),
         }
     }
 }
@@ -31,21 +47,108 @@ impl TryFrom<&str> for Market {
         match code {
             "1" => Ok(Market::KOSPI),
             "2" => Ok(Market::KOSDAQ),
+            "3" => Ok(Market::KONEX),
             _ => Err(anyhow::anyhow!("Invalid market code: {}", code)),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrumentKind {
+    Stock,
+    Preferred,
+    Etf,
+    Etn,
+    Elw,
+}
+
+/// 종목 마스터
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    //단축코드 (005930)
+    pub ticker: String,
+    //표준코드 (KR7005930003)
+    pub isin: String,
+    pub name: String,
+    pub market: Market,
+    pub kind: InstrumentKind,
+    //주문 수량 단위
+    pub lot: i64,
+    //상장일. 마스터에는 없어서 종목별로 조회하기 전까지는 None
+    pub listing_date: Option<NaiveDate>,
+    //전일 종가. 상/하한가 기준. 모르면 0
+    pub prev_close: i64,
+    //당일 상/하한가. 모르면 0
+    pub upper_limit: i64,
+    pub lower_limit: i64,
+}
+
+impl Instrument {
+    /// 주문 TR 의 IsuNo. ETN 은 Q, ELW 는 J, 나머지는 A 를 붙인다.
+    pub fn isu_no(&self) -> String {
+        let prefix = match self.kind {
+            InstrumentKind::Etn => "Q",
+            InstrumentKind::Elw => "J",
+            _ => "A",
+        };
+        format!("{}{}", prefix, self.ticker)
+    }
+
+    /// 실시간 체결 TR. 지원하지 않는 종목은 None.
+    pub fn tick_tr(&self) -> Option<&'static str> {
+        match (self.market, self.kind) {
+            (_, InstrumentKind::Elw) => None,
+            (Market::KOSPI, _) => Some("S3_"),
+            (Market::KOSDAQ, _) => Some("K3_"),
+            (Market::KONEX, _) => None,
+        }
+    }
+
+    /// 실시간 호가 TR. 지원하지 않는 종목은 None.
+    pub fn order_book_tr(&self) -> Option<&'static str> {
+        match (self.market, self.kind) {
+            (_, InstrumentKind::Elw) => None,
+            (Market::KOSPI, _) => Some("H1_"),
+            (Market::KOSDAQ, _) => Some("HA_"),
+            (Market::KONEX, _) => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum OrderAction {
     Buy,
     Sell,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum OrderType {
+    //지정가
     Limit,
+    //시장가
     Market,
+    //조건부지정가
+    ConditionalLimit,
+    //최유리지정가
+    BestLimit,
+    //최우선지정가
+    PriorityLimit,
+    //장개시전 시간외종가
+    PreOpen,
+    //시간외단일가
+    AfterHoursSingle,
+}
+
+/// 주문 조건
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeInForce {
+    //없음
+    #[default]
+    Day,
+    //IOC: 즉시 체결되지 않은 수량은 취소
+    Ioc,
+    //FOK: 전량 즉시 체결되지 않으면 전량 취소
+    Fok,
 }
 
 impl OrderAction {
@@ -57,20 +160,108 @@ impl OrderAction {
     }
 }
 
+impl TryFrom<&str> for OrderAction {
+    type Error = anyhow::Error;
+
+    fn try_from(code: &str) -> Result<Self, Self::Error> {
+        match code {
+            "1" => Ok(OrderAction::Sell),
+            "2" => Ok(OrderAction::Buy),
+            _ => Err(anyhow::anyhow!("Invalid order action code: {}", code)),
+        }
+    }
+}
+
+impl TryFrom<&str> for OrderType {
+    type Error = anyhow::Error;
+
+    fn try_from(code: &str) -> Result<Self, Self::Error> {
+        match code {
+            "00" => Ok(OrderType::Limit),
+            "03" => Ok(OrderType::Market),
+            "05" => Ok(OrderType::ConditionalLimit),
+            "06" => Ok(OrderType::BestLimit),
+            "07" => Ok(OrderType::PriorityLimit),
+            "61" => Ok(OrderType::PreOpen),
+            "82" => Ok(OrderType::AfterHoursSingle),
+            _ => Err(anyhow::anyhow!("Invalid order type code: {}", code)),
+        }
+    }
+}
+
 impl OrderType {
     fn as_str(&self) -> &str {
         match self {
             OrderType::Limit => "00",
             OrderType::Market => "03",
+            OrderType::ConditionalLimit => "05",
+            OrderType::BestLimit => "06",
+            OrderType::PriorityLimit => "07",
+            OrderType::PreOpen => "61",
+            OrderType::AfterHoursSingle => "82",
+        }
+    }
+
+    /// 주문 가격을 직접 정하는 유형. 나머지는 가격 0 으로 보낸다.
+    pub fn has_price(&self) -> bool {
+        matches!(
+            self,
+            OrderType::Limit | OrderType::ConditionalLimit | OrderType::AfterHoursSingle
+        )
+    }
+
+    /// 가격 유형과 주문 조건 조합을 확인한다.
+    /// IOC/FOK 는 지정가, 시장가, 최유리지정가에만 붙일 수 있다.
+    pub fn validate(&self, time_in_force: TimeInForce, price: i64) -> Result<()> {
+        if time_in_force != TimeInForce::Day
+            && !matches!(
+                self,
+                OrderType::Limit | OrderType::Market | OrderType::BestLimit
+            )
+        {
+            return Err(anyhow::anyhow!(
+                "{:?} cannot be used with {:?} order",
+                time_in_force,
+                self
+            ));
+        }
+        if self.has_price() && price <= 0 {
+            return Err(anyhow::anyhow!("{:?} order requires a price", self));
         }
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+impl TryFrom<&str> for TimeInForce {
+    type Error = anyhow::Error;
+
+    fn try_from(code: &str) -> Result<Self, Self::Error> {
+        match code {
+            "0" => Ok(TimeInForce::Day),
+            "1" => Ok(TimeInForce::Ioc),
+            "2" => Ok(TimeInForce::Fok),
+            _ => Err(anyhow::anyhow!("Invalid order condition code: {}", code)),
+        }
+    }
+}
+
+impl TimeInForce {
+    fn as_str(&self) -> &str {
+        match self {
+            TimeInForce::Day => "0",
+            TimeInForce::Ioc => "1",
+            TimeInForce::Fok => "2",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum OrderResultType {
     //접수
     Wait,
-    //성공
+    //일부 체결
+    PartiallyFilled,
+    //전량 체결
     Success,
     //취소
     Cancel,
@@ -80,20 +271,91 @@ pub enum OrderResultType {
     Denied,
 }
 
-#[derive(Debug)]
+/// 체결 한 건
+#[derive(Clone, Debug)]
+pub struct Fill {
+    pub action: OrderAction,
+    pub quantity: i64,
+    pub price: i64,
+    pub time: NaiveTime,
+    //이 체결 후 남은 미체결 수량
+    pub remaining: i64,
+}
+
+#[derive(Clone, Debug)]
 pub struct OrderResult {
-    id: String,
-    result: OrderResultType,
+    pub id: String,
+    pub result: OrderResultType,
+    //정정/취소 시 원주문번호
+    pub original_id: Option<String>,
+    pub ticker: Option<String>,
+    //체결(SC1) 일 때만 있다
+    pub fill: Option<Fill>,
+}
+
+impl OrderResult {
+    fn new(id: String, result: OrderResultType) -> Self {
+        Self {
+            id,
+            result,
+            original_id: None,
+            ticker: None,
+            fill: None,
+        }
+    }
+
+    fn filled(id: String, ticker: String, fill: Fill) -> Self {
+        let result = if fill.remaining > 0 {
+            OrderResultType::PartiallyFilled
+        } else {
+            OrderResultType::Success
+        };
+        Self {
+            ticker: Some(ticker),
+            fill: Some(fill),
+            ..Self::new(id, result)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrderStatus {
+    //접수
+    Accepted,
+    //일부 체결
+    PartiallyFilled,
+    //전량 체결
+    Filled,
+    //정정되어 새 주문번호로 넘어감
+    Amended,
+    //취소
+    Cancelled,
+    //거부
+    Rejected,
+}
+
+impl OrderStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::Accepted | OrderStatus::PartiallyFilled)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Order {
-    id: i64,
-    symbol: String,
-    quantity: i64,
-    price: i64,
-    action: OrderAction,
-    order_type: OrderType,
+    pub id: i64,
+    pub symbol: String,
+    pub quantity: i64,
+    pub price: i64,
+    pub action: OrderAction,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    //정정 주문이면 원주문번호
+    pub original_id: Option<i64>,
+    pub status: OrderStatus,
+    pub filled_quantity: i64,
+    pub remaining_quantity: i64,
+    //평균 체결가. 체결이 없으면 0
+    pub filled_price: i64,
 }
 
 impl Order {
@@ -112,25 +374,151 @@ impl Order {
             price,
             action,
             order_type,
+            time_in_force: TimeInForce::Day,
+            original_id: None,
+            status: OrderStatus::Accepted,
+            filled_quantity: 0,
+            remaining_quantity: quantity,
+            filled_price: 0,
+        }
+    }
+
+    // 정정으로 새 주문번호를 받은 주문
+    fn amended(&self, id: i64, quantity: i64, price: i64) -> Self {
+        Self {
+            id,
+            quantity,
+            price,
+            time_in_force: self.time_in_force,
+            original_id: Some(self.id),
+            ..Self::new(
+                id,
+                self.symbol.clone(),
+                quantity,
+                price,
+                self.action,
+                self.order_type,
+            )
+        }
+    }
+}
+
+/// 주문 조회 범위
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OrderScope {
+    #[default]
+    All,
+    Filled,
+    Unfilled,
+}
+
+/// `Broker::get_orders` 조건. 비어 있으면 당일 전체 주문.
+#[derive(Clone, Debug, Default)]
+pub struct OrderFilter {
+    pub ticker: Option<String>,
+    pub action: Option<OrderAction>,
+    pub scope: OrderScope,
+}
+
+impl OrderFilter {
+    pub fn unfilled() -> Self {
+        Self {
+            scope: OrderScope::Unfilled,
+            ..Self::default()
+        }
+    }
+
+    pub fn matches(&self, order: &Order) -> bool {
+        self.ticker.as_ref().is_none_or(|t| *t == order.symbol)
+            && self.action.is_none_or(|a| a == order.action)
+            && match self.scope {
+                OrderScope::All => true,
+                OrderScope::Filled => order.filled_quantity > 0,
+                OrderScope::Unfilled => order.status.is_open(),
+            }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceSign {
+    UpperLimit,
+    Up,
+    Unchanged,
+    LowerLimit,
+    Down,
+}
+
+impl TryFrom<&str> for PriceSign {
+    type Error = anyhow::Error;
+
+    fn try_from(code: &str) -> Result<Self, Self::Error> {
+        match code {
+            "1" => Ok(PriceSign::UpperLimit),
+            "2" => Ok(PriceSign::Up),
+            "3" => Ok(PriceSign::Unchanged),
+            "4" => Ok(PriceSign::LowerLimit),
+            "5" => Ok(PriceSign::Down),
+            _ => Err(anyhow::anyhow!("Invalid sign code: {}", code)),
+        }
+    }
+}
+
+// 체결 주체. 매수 체결은 매도 호가를, 매도 체결은 매수 호가를 친 거래다.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+    //단일가 체결 등 주체가 없는 체결 (cgubun 이 +, - 가 아님)
+    Unknown,
+}
+
+impl From<&str> for TradeSide {
+    fn from(code: &str) -> Self {
+        match code {
+            "+" => TradeSide::Buy,
+            "-" => TradeSide::Sell,
+            _ => TradeSide::Unknown,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Tick {
-    pub price: String,
-    #[serde(rename = "cvolume")]
-    pub volume: String,
-    #[serde(rename = "shcode")]
     pub ticker: String,
+    pub price: i64,
+    //체결량
+    pub volume: i64,
+    //누적거래량
+    pub cumulative_volume: i64,
+    //체결시간 (거래소 시각)
+    pub time: NaiveTime,
+    //전일대비. 하락이면 음수
+    pub change: i64,
+    pub sign: PriceSign,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub side: TradeSide,
+    //수신시각
+    pub received_at: DateTime<Utc>,
 }
 
 impl Tick {
-    pub fn new(ticker: String, price: String, volume: String) -> Self {
+    pub fn new(ticker: String, price: i64, volume: i64) -> Self {
+        let received_at = Utc::now();
         Self {
+            ticker,
             price,
             volume,
-            ticker,
+            cumulative_volume: volume,
+            time: received_at.with_timezone(&Local).time(),
+            change: 0,
+            sign: PriceSign::Unchanged,
+            open: price,
+            high: price,
+            low: price,
+            side: TradeSide::Buy,
+            received_at,
         }
     }
 }
@@ -139,12 +527,101 @@ impl Display for Tick {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "ticker: {}, price: {}, volume: {}",
-            self.ticker, self.price, self.volume
+            "ticker: {}, time: {}, price: {}, change: {}, volume: {}, side: {:?}",
+            self.ticker, self.time, self.price, self.change, self.volume, self.side
         )
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price: i64,
+    pub quantity: i64,
+}
+
+// 10단계 호가. asks/bids 의 0번이 최우선 호가다.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderBook {
+    pub ticker: String,
+    //호가시간 (거래소 시각)
+    pub time: NaiveTime,
+    pub asks: Vec<BookLevel>,
+    pub bids: Vec<BookLevel>,
+    //총매도호가잔량
+    pub total_ask_quantity: i64,
+    //총매수호가잔량
+    pub total_bid_quantity: i64,
+    pub received_at: DateTime<Utc>,
+}
+
+impl OrderBook {
+    pub fn best_ask(&self) -> Option<BookLevel> {
+        self.asks.first().copied().filter(|l| l.price > 0)
+    }
+
+    pub fn best_bid(&self) -> Option<BookLevel> {
+        self.bids.first().copied().filter(|l| l.price > 0)
+    }
+
+    pub fn spread(&self) -> Option<i64> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    pub fn mid_price(&self) -> Option<f64> {
+        Some((self.best_ask()?.price + self.best_bid()?.price) as f64 / 2.0)
+    }
+
+    /// 총잔량 불균형. 매수 우위면 양수, 매도 우위면 음수 (-1 ~ 1)
+    pub fn imbalance(&self) -> Option<f64> {
+        let total = self.total_bid_quantity + self.total_ask_quantity;
+        if total == 0 {
+            return None;
+        }
+        Some((self.total_bid_quantity - self.total_ask_quantity) as f64 / total as f64)
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    Tick,
+    OrderTransaction,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    //끊김. 재접속 전까지 데이터 공백이 생긴다.
+    Disconnected,
+    Reconnected,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConnectionEvent {
+    pub stream: StreamKind,
+    pub state: ConnectionState,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+/// 봉 주기
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CandleInterval {
+    Day,
+    //N분봉
+    Minute(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candle {
+    pub ticker: String,
+    //일봉은 해당 일자 00:00
+    pub datetime: NaiveDateTime,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub close: i64,
+    pub volume: i64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Position {
     #[serde(rename = "expcode")]
@@ -165,13 +642,59 @@ pub struct Position {
     tax: f64,
 }
 
+impl Position {
+    /// 평가금액, 손익 없이 수량과 평단만 있는 잔고 (전략별 잔고 등)
+    pub fn new(ticker: String, quantity: i64, average_price: f64) -> Self {
+        Self {
+            ticker,
+            quantity,
+            evaluation_price: average_price * quantity as f64,
+            average_price,
+            profit: 0.0,
+            rate_of_return: "0.00".to_string(),
+            fee: 0.0,
+            tax: 0.0,
+        }
+    }
+}
+
 #[async_trait]
 pub trait Broker: Send + Sync {
-    async fn get_tickers(&self) -> Result<HashMap<String, Market>>;
+    /// 단축코드별 종목 마스터
+    async fn get_instruments(&self) -> Result<HashMap<String, Instrument>>;
+    async fn get_instrument(&self, ticker: &str) -> Result<Instrument> {
+        self.get_instruments()
+            .await?
+            .get(ticker)
+            .cloned()
+            .ok_or_else(|| error::BrokerError::InvalidTicker(ticker.to_string()).into())
+    }
     async fn subscribe(&self, ticker: &str) -> Result<()>;
+    async fn unsubscribe(&self, ticker: &str) -> Result<()>;
+    async fn subscriptions(&self) -> Vec<String>;
+    async fn subscribe_order_book(&self, ticker: &str) -> Result<()>;
+    async fn unsubscribe_order_book(&self, ticker: &str) -> Result<()>;
+    fn order_book_stream(&self) -> broadcast::Receiver<OrderBook>;
     async fn get_balance(&self) -> Result<i64>;
     async fn get_positions(&self) -> Result<Vec<Position>>;
+    /// `from` ~ `to` (포함) 구간의 봉. 오래된 것부터 정렬해서 돌려준다.
+    async fn get_candles(
+        &self,
+        ticker: &str,
+        interval: CandleInterval,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Candle>>;
     async fn order_cancel(&self, order: Order) -> Result<()>;
+    /// 당일 주문 목록
+    async fn get_orders(&self, filter: OrderFilter) -> Result<Vec<Order>>;
+    /// 미체결 주문
+    async fn get_open_orders(&self) -> Result<Vec<Order>> {
+        self.get_orders(OrderFilter::unfilled()).await
+    }
+    /// `date` 에 체결된 주문. 체결 수량과 평균 체결가가 채워진다.
+    async fn get_executions(&self, date: NaiveDate) -> Result<Vec<Order>>;
+    async fn order_modify(&self, order: Order, new_qty: i64, new_price: i64) -> Result<Order>;
     async fn get_access_token(&self) -> Result<String>;
     async fn connect_websocket(
         &self,
@@ -184,9 +707,17 @@ pub trait Broker: Send + Sync {
         price: i64,
         order_action: OrderAction,
         order_type: OrderType,
+        time_in_force: TimeInForce,
     ) -> Result<Order>;
     async fn connect_websocket_order_transaction(
         &self,
         token: CancellationToken,
     ) -> Result<Receiver<OrderResult>>;
+    fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent>;
+    /// 장 운영 상태 변경. 체결 웹소켓에 접속해 있어야 받는다.
+    fn session_events(&self) -> broadcast::Receiver<session::SessionEvent>;
+    /// 종목의 VI, 매매정지 상태
+    fn tradability(&self, ticker: &str) -> tradability::Tradability;
+    /// VI 발동/해제, 매매정지 변경. 체결 웹소켓에 접속해 있어야 받는다.
+    fn tradability_events(&self) -> broadcast::Receiver<tradability::TradabilityEvent>;
 }
@@ -0,0 +1,759 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{Local, NaiveDate};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::broker::session::SessionEvent;
+use crate::broker::tradability::{Tradability, TradabilityEvent};
+use crate::broker::{
+    Broker, Candle, CandleInterval, ConnectionEvent, Fill, Instrument, Order, OrderAction,
+    OrderBook, OrderFilter, OrderResult, OrderResultType, OrderScope, OrderStatus, OrderType,
+    Position, Tick, TimeInForce,
+};
+
+/// 모의 체결 브로커.
+/// 시세와 종목 정보는 `feed` 에서 받고, 주문은 자체 현금/잔고 장부에서 틱 가격으로 체결한다.
+#[derive(Clone)]
+pub struct PaperBroker {
+    feed: Arc<dyn Broker>,
+    book: Arc<Mutex<PaperBook>>,
+    events: Arc<Mutex<Option<Sender<OrderResult>>>>,
+}
+
+impl PaperBroker {
+    pub fn new(feed: Arc<dyn Broker>, cash: i64) -> Self {
+        Self {
+            feed,
+            book: Arc::new(Mutex::new(PaperBook::new(cash))),
+            events: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn emit(events: &Mutex<Option<Sender<OrderResult>>>, results: Vec<OrderResult>) {
+        let events = events.lock().await;
+        if let Some(tx) = events.as_ref() {
+            for result in results {
+                if let Err(e) = tx.send(result).await {
+                    error!("Failed to send paper order result: {}", e);
+                }
+            }
+        }
+    }
+}
+
+struct Holding {
+    quantity: i64,
+    average_price: f64,
+}
+
+struct PaperBook {
+    cash: i64,
+    next_id: i64,
+    holdings: HashMap<String, Holding>,
+    open_orders: Vec<Order>,
+    // 당일 전체 주문 (상태 갱신 포함)
+    history: Vec<Order>,
+    last_prices: HashMap<String, i64>,
+}
+
+impl PaperBook {
+    fn new(cash: i64) -> Self {
+        Self {
+            cash,
+            next_id: 1,
+            holdings: HashMap::new(),
+            open_orders: Vec::new(),
+            history: Vec::new(),
+            last_prices: HashMap::new(),
+        }
+    }
+
+    // 미체결 지정가 매수에 묶인 금액을 제외한 주문가능금액
+    fn available_cash(&self) -> i64 {
+        let reserved: i64 = self
+            .open_orders
+            .iter()
+            .filter(|o| matches!(o.action, OrderAction::Buy))
+            .filter(|o| o.order_type.has_price())
+            .map(|o| o.quantity * o.price)
+            .sum();
+        self.cash - reserved
+    }
+
+    // 미체결 매도 수량을 제외한 매도가능수량
+    fn available_quantity(&self, symbol: &str) -> i64 {
+        let held = self.holdings.get(symbol).map_or(0, |h| h.quantity);
+        let reserved: i64 = self
+            .open_orders
+            .iter()
+            .filter(|o| o.symbol == symbol && matches!(o.action, OrderAction::Sell))
+            .map(|o| o.quantity)
+            .sum();
+        held - reserved
+    }
+
+    fn record(&mut self, order: &Order) {
+        match self.history.iter_mut().find(|o| o.id == order.id) {
+            Some(recorded) => *recorded = order.clone(),
+            None => self.history.push(order.clone()),
+        }
+    }
+
+    fn set_status(&mut self, id: i64, status: OrderStatus) {
+        if let Some(order) = self.history.iter_mut().find(|o| o.id == id) {
+            order.status = status;
+            if !status.is_open() {
+                order.remaining_quantity = 0;
+            }
+        }
+    }
+
+    fn place(
+        &mut self,
+        symbol: &str,
+        quantity: i64,
+        price: i64,
+        action: OrderAction,
+        order_type: OrderType,
+        time_in_force: TimeInForce,
+    ) -> (Order, Vec<OrderResult>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut order = Order::new(id, symbol.to_string(), quantity, price, action, order_type);
+        order.time_in_force = time_in_force;
+
+        let mut results = vec![OrderResult::new(id.to_string(), OrderResultType::Wait)];
+
+        let denied = self.denied(symbol, quantity, price, action, order_type);
+        self.record(&order);
+        if denied {
+            self.set_status(id, OrderStatus::Rejected);
+            results.push(OrderResult::new(id.to_string(), OrderResultType::Denied));
+            return (order, results);
+        }
+
+        self.open_orders.push(order.clone());
+        if let Some(last) = self.last_prices.get(symbol).copied() {
+            results.extend(self.match_orders(symbol, last));
+        }
+        // IOC/FOK 는 바로 체결되지 않으면 취소된다. 모의 체결은 전량 체결이라 둘이 같다.
+        if time_in_force != TimeInForce::Day {
+            results.extend(self.cancel(id));
+        }
+        (order, results)
+    }
+
+    // 수량, 가격이 잘못됐거나 주문가능금액/수량을 넘는 주문
+    fn denied(
+        &self,
+        symbol: &str,
+        quantity: i64,
+        price: i64,
+        action: OrderAction,
+        order_type: OrderType,
+    ) -> bool {
+        // 가격 없는 유형은 시장가처럼 현재가로 체결한다.
+        let cost_price = if order_type.has_price() {
+            Some(price)
+        } else {
+            self.last_prices.get(symbol).copied()
+        };
+        quantity <= 0
+            || (order_type.has_price() && price <= 0)
+            || match action {
+                OrderAction::Buy => {
+                    cost_price.is_some_and(|p| quantity * p > self.available_cash())
+                }
+                OrderAction::Sell => quantity > self.available_quantity(symbol),
+            }
+    }
+
+    fn cancel(&mut self, id: i64) -> Option<OrderResult> {
+        let index = self.open_orders.iter().position(|o| o.id == id)?;
+        self.open_orders.remove(index);
+        self.set_status(id, OrderStatus::Cancelled);
+        Some(OrderResult::new(id.to_string(), OrderResultType::Cancel))
+    }
+
+    fn modify(&mut self, id: i64, quantity: i64, price: i64) -> Result<(Order, OrderResult)> {
+        let index = self
+            .open_orders
+            .iter()
+            .position(|o| o.id == id)
+            .context("order is not open")?;
+        // 정정도 신규 주문처럼 확인한다. 원주문에 묶인 금액/수량은 다시 쓸 수 있다.
+        let original = self.open_orders.remove(index);
+        if self.denied(
+            &original.symbol,
+            quantity,
+            price,
+            original.action,
+            original.order_type,
+        ) {
+            self.open_orders.insert(index, original);
+            return Err(anyhow!(
+                "amendment of order {} to {} @ {} is denied",
+                id,
+                quantity,
+                price
+            ));
+        }
+        self.open_orders.insert(index, original);
+        let new_id = self.next_id;
+        self.next_id += 1;
+        let order = self.open_orders[index].amended(new_id, quantity, price);
+        self.open_orders[index] = order.clone();
+        self.set_status(id, OrderStatus::Amended);
+        self.record(&order);
+
+        let mut result = OrderResult::new(new_id.to_string(), OrderResultType::Edit);
+        result.original_id = Some(id.to_string());
+        Ok((order, result))
+    }
+
+    fn on_tick(&mut self, symbol: &str, price: i64) -> Vec<OrderResult> {
+        self.last_prices.insert(symbol.to_string(), price);
+        self.match_orders(symbol, price)
+    }
+
+    fn match_orders(&mut self, symbol: &str, price: i64) -> Vec<OrderResult> {
+        let (fillable, resting): (Vec<Order>, Vec<Order>) =
+            self.open_orders.drain(..).partition(|o| {
+                o.symbol == symbol
+                    && match o.action {
+                        _ if !o.order_type.has_price() => true,
+                        OrderAction::Buy => price <= o.price,
+                        OrderAction::Sell => price >= o.price,
+                    }
+            });
+        self.open_orders = resting;
+        fillable
+            .into_iter()
+            .map(|order| self.fill(&order, price))
+            .collect()
+    }
+
+    fn fill(&mut self, order: &Order, price: i64) -> OrderResult {
+        let amount = order.quantity * price;
+        match order.action {
+            OrderAction::Buy => {
+                if amount > self.available_cash() {
+                    self.set_status(order.id, OrderStatus::Rejected);
+                    return OrderResult::new(order.id.to_string(), OrderResultType::Denied);
+                }
+                self.cash -= amount;
+                let holding = self
+                    .holdings
+                    .entry(order.symbol.clone())
+                    .or_insert(Holding {
+                        quantity: 0,
+                        average_price: 0.0,
+                    });
+                let total = holding.quantity + order.quantity;
+                holding.average_price = (holding.average_price * holding.quantity as f64
+                    + amount as f64)
+                    / total as f64;
+                holding.quantity = total;
+            }
+            OrderAction::Sell => {
+                self.cash += amount;
+                if let Some(holding) = self.holdings.get_mut(&order.symbol) {
+                    holding.quantity -= order.quantity;
+                    if holding.quantity <= 0 {
+                        self.holdings.remove(&order.symbol);
+                    }
+                }
+            }
+        }
+        if let Some(recorded) = self.history.iter_mut().find(|o| o.id == order.id) {
+            recorded.status = OrderStatus::Filled;
+            recorded.filled_quantity = order.quantity;
+            recorded.remaining_quantity = 0;
+            recorded.filled_price = price;
+        }
+        let fill = Fill {
+            action: order.action,
+            quantity: order.quantity,
+            price,
+            time: Local::now().time(),
+            remaining: 0,
+        };
+        OrderResult::filled(order.id.to_string(), order.symbol.clone(), fill)
+    }
+
+    fn positions(&self) -> Vec<Position> {
+        self.holdings
+            .iter()
+            .map(|(ticker, holding)| {
+                let last = self
+                    .last_prices
+                    .get(ticker)
+                    .map_or(holding.average_price, |p| *p as f64);
+                let evaluation_price = last * holding.quantity as f64;
+                let profit = (last - holding.average_price) * holding.quantity as f64;
+                Position {
+                    ticker: ticker.clone(),
+                    quantity: holding.quantity,
+                    evaluation_price,
+                    average_price: holding.average_price,
+                    profit,
+                    rate_of_return: format!("{:.2}", (last / holding.average_price - 1.0) * 100.0),
+                    fee: 0.0,
+                    tax: 0.0,
+                }
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Broker for PaperBroker {
+    async fn get_instruments(&self) -> Result<HashMap<String, Instrument>> {
+        self.feed.get_instruments().await
+    }
+
+    async fn get_instrument(&self, ticker: &str) -> Result<Instrument> {
+        self.feed.get_instrument(ticker).await
+    }
+
+    async fn subscribe(&self, ticker: &str) -> Result<()> {
+        self.feed.subscribe(ticker).await
+    }
+
+    async fn unsubscribe(&self, ticker: &str) -> Result<()> {
+        self.feed.unsubscribe(ticker).await
+    }
+
+    async fn subscriptions(&self) -> Vec<String> {
+        self.feed.subscriptions().await
+    }
+
+    async fn subscribe_order_book(&self, ticker: &str) -> Result<()> {
+        self.feed.subscribe_order_book(ticker).await
+    }
+
+    async fn unsubscribe_order_book(&self, ticker: &str) -> Result<()> {
+        self.feed.unsubscribe_order_book(ticker).await
+    }
+
+    fn order_book_stream(&self) -> broadcast::Receiver<OrderBook> {
+        self.feed.order_book_stream()
+    }
+
+    async fn get_balance(&self) -> Result<i64> {
+        Ok(self.book.lock().await.available_cash())
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>> {
+        Ok(self.book.lock().await.positions())
+    }
+
+    async fn get_candles(
+        &self,
+        ticker: &str,
+        interval: CandleInterval,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Candle>> {
+        self.feed.get_candles(ticker, interval, from, to).await
+    }
+
+    async fn order_cancel(&self, order: Order) -> Result<()> {
+        let result = self
+            .book
+            .lock()
+            .await
+            .cancel(order.id)
+            .context("order is not open")?;
+        Self::emit(&self.events, vec![result]).await;
+        Ok(())
+    }
+
+    async fn get_orders(&self, filter: OrderFilter) -> Result<Vec<Order>> {
+        let book = self.book.lock().await;
+        Ok(book
+            .history
+            .iter()
+            .filter(|order| filter.matches(order))
+            .cloned()
+            .collect())
+    }
+
+    // 장부는 메모리에만 있으므로 당일 체결만 알 수 있다.
+    async fn get_executions(&self, date: NaiveDate) -> Result<Vec<Order>> {
+        if date != Local::now().date_naive() {
+            return Ok(Vec::new());
+        }
+        self.get_orders(OrderFilter {
+            scope: OrderScope::Filled,
+            ..OrderFilter::default()
+        })
+        .await
+    }
+
+    async fn order_modify(&self, order: Order, new_qty: i64, new_price: i64) -> Result<Order> {
+        let mut book = self.book.lock().await;
+        let (order, result) = book.modify(order.id, new_qty, new_price)?;
+        let mut results = vec![result];
+        if let Some(last) = book.last_prices.get(&order.symbol).copied() {
+            results.extend(book.match_orders(&order.symbol, last));
+        }
+        drop(book);
+        Self::emit(&self.events, results).await;
+        Ok(order)
+    }
+
+    async fn get_access_token(&self) -> Result<String> {
+        self.feed.get_access_token().await
+    }
+
+    async fn connect_websocket(&self, token: CancellationToken) -> Result<Receiver<Tick>> {
+        let mut feed = self.feed.connect_websocket(token.clone()).await?;
+        let book = Arc::clone(&self.book);
+        let events = Arc::clone(&self.events);
+
+        let (tx, rx) = channel::<Tick>(100);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    tick = feed.recv() => {
+                        let Some(tick) = tick else {
+                            info!("stop receive paper tick data");
+                            break;
+                        };
+                        let results = book.lock().await.on_tick(&tick.ticker, tick.price);
+                        Self::emit(&events, results).await;
+                        if tx.send(tick).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = token.cancelled() => {
+                        info!("receive signal");
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn order(
+        &self,
+        ticker: &str,
+        amount: i64,
+        price: i64,
+        order_action: OrderAction,
+        order_type: OrderType,
+        time_in_force: TimeInForce,
+    ) -> Result<Order> {
+        order_type.validate(time_in_force, price)?;
+        let (order, results) = self.book.lock().await.place(
+            ticker,
+            amount,
+            price,
+            order_action,
+            order_type,
+            time_in_force,
+        );
+        Self::emit(&self.events, results).await;
+        Ok(order)
+    }
+
+    async fn connect_websocket_order_transaction(
+        &self,
+        token: CancellationToken,
+    ) -> Result<Receiver<OrderResult>> {
+        let (tx, rx) = channel::<OrderResult>(100);
+        *self.events.lock().await = Some(tx);
+
+        let events = Arc::clone(&self.events);
+        tokio::spawn(async move {
+            token.cancelled().await;
+            info!("receive signal");
+            *events.lock().await = None;
+        });
+        Ok(rx)
+    }
+
+    fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.feed.connection_events()
+    }
+
+    fn session_events(&self) -> broadcast::Receiver<SessionEvent> {
+        self.feed.session_events()
+    }
+
+    fn tradability(&self, ticker: &str) -> Tradability {
+        self.feed.tradability(ticker)
+    }
+
+    fn tradability_events(&self) -> broadcast::Receiver<TradabilityEvent> {
+        self.feed.tradability_events()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::broker::lssec::LsSecClient;
+    use crate::broker::mock::MockLsServer;
+
+    fn results(results: &[OrderResult]) -> Vec<String> {
+        results.iter().map(|r| format!("{:?}", r.result)).collect()
+    }
+
+    #[test]
+    fn test_limit_order_fills_when_price_crosses() {
+        let mut book = PaperBook::new(100_000);
+        book.on_tick("005930", 10_000);
+
+        let (order, events) = book.place(
+            "005930",
+            5,
+            9_000,
+            OrderAction::Buy,
+            OrderType::Limit,
+            TimeInForce::Day,
+        );
+        assert_eq!(results(&events), vec!["Wait"]);
+        assert_eq!(book.available_cash(), 55_000);
+
+        assert!(book.on_tick("005930", 9_500).is_empty());
+        let events = book.on_tick("005930", 8_900);
+        assert_eq!(results(&events), vec!["Success"]);
+        assert_eq!(events[0].id, order.id.to_string());
+        let fill = events[0].fill.as_ref().unwrap();
+        assert_eq!((fill.quantity, fill.price, fill.remaining), (5, 8_900, 0));
+        assert_eq!(book.cash, 100_000 - 5 * 8_900);
+
+        let positions = book.positions();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].quantity, 5);
+        assert_eq!(positions[0].average_price, 8_900.0);
+    }
+
+    #[test]
+    fn test_market_order_and_sell() {
+        let mut book = PaperBook::new(100_000);
+        book.on_tick("005930", 10_000);
+
+        let (_, events) = book.place(
+            "005930",
+            3,
+            0,
+            OrderAction::Buy,
+            OrderType::Market,
+            TimeInForce::Day,
+        );
+        assert_eq!(results(&events), vec!["Wait", "Success"]);
+
+        let (_, events) = book.place(
+            "005930",
+            4,
+            0,
+            OrderAction::Sell,
+            OrderType::Market,
+            TimeInForce::Day,
+        );
+        assert_eq!(results(&events), vec!["Wait", "Denied"]);
+
+        book.on_tick("005930", 11_000);
+        let (_, events) = book.place(
+            "005930",
+            3,
+            0,
+            OrderAction::Sell,
+            OrderType::Market,
+            TimeInForce::Day,
+        );
+        assert_eq!(results(&events), vec!["Wait", "Success"]);
+        assert_eq!(book.cash, 103_000);
+        assert!(book.positions().is_empty());
+    }
+
+    #[test]
+    fn test_modify_reprices_resting_order() {
+        let mut book = PaperBook::new(100_000);
+        book.on_tick("005930", 10_000);
+        let (order, _) = book.place(
+            "005930",
+            2,
+            9_000,
+            OrderAction::Buy,
+            OrderType::Limit,
+            TimeInForce::Day,
+        );
+
+        let (amended, result) = book.modify(order.id, 3, 9_500).unwrap();
+        assert_eq!(format!("{:?}", result.result), "Edit");
+        assert_eq!(result.original_id, Some(order.id.to_string()));
+        assert_eq!(amended.original_id, Some(order.id));
+        assert_eq!(book.available_cash(), 100_000 - 3 * 9_500);
+        assert!(book.modify(order.id, 1, 9_000).is_err());
+
+        let events = book.on_tick("005930", 9_500);
+        assert_eq!(events[0].id, amended.id.to_string());
+    }
+
+    #[test]
+    fn test_modify_checks_cash_and_holdings() {
+        let mut book = PaperBook::new(100_000);
+        book.on_tick("005930", 10_000);
+        let (buy, _) = book.place(
+            "005930",
+            5,
+            9_000,
+            OrderAction::Buy,
+            OrderType::Limit,
+            TimeInForce::Day,
+        );
+        // 원주문에 묶인 45,000 원은 다시 쓸 수 있지만 100,000 원을 넘게 올릴 수는 없다.
+        assert!(book.modify(buy.id, 11, 9_500).is_err());
+        assert_eq!(book.available_cash(), 55_000);
+        let (buy, _) = book.modify(buy.id, 10, 9_500).unwrap();
+        assert_eq!(book.available_cash(), 5_000);
+
+        let events = book.on_tick("005930", 9_500);
+        assert_eq!(events[0].id, buy.id.to_string());
+        let (sell, _) = book.place(
+            "005930",
+            5,
+            11_000,
+            OrderAction::Sell,
+            OrderType::Limit,
+            TimeInForce::Day,
+        );
+        assert!(book.modify(sell.id, 11, 11_000).is_err());
+        let (sell, _) = book.modify(sell.id, 10, 11_000).unwrap();
+        assert!(book.modify(sell.id, 0, 11_000).is_err());
+        assert_eq!(book.available_quantity("005930"), 0);
+    }
+
+    #[test]
+    fn test_denied_and_cancel() {
+        let mut book = PaperBook::new(10_000);
+
+        let (_, events) = book.place(
+            "005930",
+            2,
+            9_000,
+            OrderAction::Buy,
+            OrderType::Limit,
+            TimeInForce::Day,
+        );
+        assert_eq!(results(&events), vec!["Wait", "Denied"]);
+
+        let (order, _) = book.place(
+            "005930",
+            1,
+            9_000,
+            OrderAction::Buy,
+            OrderType::Limit,
+            TimeInForce::Day,
+        );
+        let cancelled = book.cancel(order.id).expect("order should be open");
+        assert_eq!(format!("{:?}", cancelled.result), "Cancel");
+        assert!(book.cancel(order.id).is_none());
+        assert_eq!(book.available_cash(), 10_000);
+    }
+
+    #[test]
+    fn test_ioc_cancels_unfilled() {
+        let mut book = PaperBook::new(100_000);
+        book.on_tick("005930", 10_000);
+
+        let (order, events) = book.place(
+            "005930",
+            2,
+            9_000,
+            OrderAction::Buy,
+            OrderType::Limit,
+            TimeInForce::Ioc,
+        );
+        assert_eq!(results(&events), vec!["Wait", "Cancel"]);
+        assert!(book.cancel(order.id).is_none());
+        assert_eq!(book.available_cash(), 100_000);
+
+        let (_, events) = book.place(
+            "005930",
+            2,
+            0,
+            OrderAction::Buy,
+            OrderType::BestLimit,
+            TimeInForce::Fok,
+        );
+        assert_eq!(results(&events), vec!["Wait", "Success"]);
+        assert_eq!(book.cash, 80_000);
+    }
+
+    #[tokio::test]
+    async fn test_order_history() {
+        let server = MockLsServer::start().await.unwrap();
+        let feed = LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment());
+        let broker = PaperBroker::new(Arc::new(feed), 100_000);
+        broker.book.lock().await.on_tick("005930", 10_000);
+
+        let bought = broker
+            .order(
+                "005930",
+                2,
+                0,
+                OrderAction::Buy,
+                OrderType::Market,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let resting = broker
+            .order(
+                "005930",
+                1,
+                9_000,
+                OrderAction::Buy,
+                OrderType::Limit,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+        let denied = broker
+            .order(
+                "005930",
+                100,
+                0,
+                OrderAction::Buy,
+                OrderType::Market,
+                TimeInForce::Day,
+            )
+            .await
+            .unwrap();
+
+        let orders = broker.get_orders(OrderFilter::default()).await.unwrap();
+        let statuses: Vec<_> = orders.iter().map(|o| (o.id, o.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (bought.id, OrderStatus::Filled),
+                (resting.id, OrderStatus::Accepted),
+                (denied.id, OrderStatus::Rejected),
+            ]
+        );
+        let open = broker.get_open_orders().await.unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].id, resting.id);
+
+        let executions = broker
+            .get_executions(Local::now().date_naive())
+            .await
+            .unwrap();
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].filled_price, 10_000);
+    }
+}
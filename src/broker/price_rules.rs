@@ -0,0 +1,220 @@
+//! KRX 호가가격단위와 가격제한폭.
+//! 주문 전에 가격을 호가 단위로 맞추고, 상/하한가를 벗어난 주문을 거른다.
+
+use anyhow::{anyhow, Result};
+
+use crate::broker::{Instrument, InstrumentKind};
+
+// 가격제한폭 ±30%
+const PRICE_LIMIT_PERCENT: i64 = 30;
+
+// 주식 호가가격단위 (2023.01.25 부터 코스피/코스닥/코넥스 공통). (가격 상한, 호가 단위)
+const STOCK_TICKS: [(i64, i64); 6] = [
+    (2_000, 1),
+    (5_000, 5),
+    (20_000, 10),
+    (50_000, 50),
+    (200_000, 100),
+    (500_000, 500),
+];
+const STOCK_TOP_TICK: i64 = 1_000;
+// ETF, ETN, ELW 는 가격과 상관없이 5원
+const FUND_TICK: i64 = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+    Nearest,
+}
+
+/// `price` 에서의 호가 단위
+pub fn tick_size(instrument: &Instrument, price: i64) -> i64 {
+    match instrument.kind {
+        InstrumentKind::Etf | InstrumentKind::Etn | InstrumentKind::Elw => FUND_TICK,
+        InstrumentKind::Stock | InstrumentKind::Preferred => STOCK_TICKS
+            .iter()
+            .find(|(below, _)| price < *below)
+            .map_or(STOCK_TOP_TICK, |(_, tick)| *tick),
+    }
+}
+
+/// 가격을 호가 단위에 맞춘다.
+pub fn round_to_tick(instrument: &Instrument, price: i64, rounding: Rounding) -> i64 {
+    let tick = tick_size(instrument, price);
+    let down = price - price.rem_euclid(tick);
+    if down == price {
+        return price;
+    }
+    // 구간 경계는 항상 위 구간의 호가 단위의 배수라 올림해도 호가에 맞는다.
+    let up = down + tick;
+    match rounding {
+        Rounding::Down => down,
+        Rounding::Up => up,
+        Rounding::Nearest if price - down < up - price => down,
+        Rounding::Nearest => up,
+    }
+}
+
+/// 당일 상/하한가
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PriceLimits {
+    pub upper: i64,
+    pub lower: i64,
+}
+
+impl PriceLimits {
+    /// 기준가(전일 종가) 에 30% 를 더하고 뺀 뒤, 상한가는 그 가격의 호가 단위로 내리고 하한가는 올린다.
+    pub fn from_prev_close(instrument: &Instrument, prev_close: i64) -> Self {
+        let width = prev_close * PRICE_LIMIT_PERCENT / 100;
+        Self {
+            upper: round_to_tick(instrument, prev_close + width, Rounding::Down),
+            lower: round_to_tick(instrument, (prev_close - width).max(1), Rounding::Up),
+        }
+    }
+
+    pub fn contains(&self, price: i64) -> bool {
+        (self.lower..=self.upper).contains(&price)
+    }
+
+    /// 가격을 상/하한가 안으로 당긴다.
+    pub fn clamp(&self, price: i64) -> i64 {
+        price.clamp(self.lower, self.upper)
+    }
+}
+
+impl Instrument {
+    /// 마스터의 상/하한가. 없으면 전일 종가로 계산하고, 전일 종가도 모르면 None
+    pub fn price_limits(&self) -> Option<PriceLimits> {
+        if self.upper_limit > 0 && self.lower_limit > 0 {
+            return Some(PriceLimits {
+                upper: self.upper_limit,
+                lower: self.lower_limit,
+            });
+        }
+        (self.prev_close > 0).then(|| PriceLimits::from_prev_close(self, self.prev_close))
+    }
+}
+
+/// 호가 단위와 상/하한가를 확인한다.
+pub fn validate_price(instrument: &Instrument, price: i64) -> Result<()> {
+    if price <= 0 {
+        return Err(anyhow!("invalid price {} for {}", price, instrument.ticker));
+    }
+    let tick = tick_size(instrument, price);
+    if price % tick != 0 {
+        return Err(anyhow!(
+            "price {} is not on the {} won tick for {}",
+            price,
+            tick,
+            instrument.ticker
+        ));
+    }
+    if let Some(limits) = instrument.price_limits() {
+        if !limits.contains(price) {
+            return Err(anyhow!(
+                "price {} is outside the daily limits {}~{} for {}",
+                price,
+                limits.lower,
+                limits.upper,
+                instrument.ticker
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::broker::Market;
+
+    fn instrument(kind: InstrumentKind, prev_close: i64) -> Instrument {
+        Instrument {
+            ticker: "005930".to_string(),
+            isin: "KR7005930003".to_string(),
+            name: "삼성전자".to_string(),
+            market: Market::KOSPI,
+            kind,
+            lot: 1,
+            listing_date: None,
+            prev_close,
+            upper_limit: 0,
+            lower_limit: 0,
+        }
+    }
+
+    #[test]
+    fn test_tick_size() {
+        let stock = instrument(InstrumentKind::Stock, 0);
+        for (price, tick) in [
+            (1_999, 1),
+            (2_000, 5),
+            (19_990, 10),
+            (20_000, 50),
+            (70_000, 100),
+            (200_000, 500),
+            (500_000, 1_000),
+        ] {
+            assert_eq!(tick_size(&stock, price), tick, "price {}", price);
+        }
+        assert_eq!(tick_size(&instrument(InstrumentKind::Etf, 0), 35_000), 5);
+    }
+
+    #[test]
+    fn test_round_to_tick() {
+        let stock = instrument(InstrumentKind::Stock, 0);
+        assert_eq!(round_to_tick(&stock, 70_040, Rounding::Down), 70_000);
+        assert_eq!(round_to_tick(&stock, 70_040, Rounding::Up), 70_100);
+        assert_eq!(round_to_tick(&stock, 70_040, Rounding::Nearest), 70_000);
+        assert_eq!(round_to_tick(&stock, 70_060, Rounding::Nearest), 70_100);
+        assert_eq!(round_to_tick(&stock, 4_998, Rounding::Up), 5_000);
+        assert_eq!(round_to_tick(&stock, 70_000, Rounding::Up), 70_000);
+    }
+
+    #[test]
+    fn test_price_limits() {
+        let stock = instrument(InstrumentKind::Stock, 70_000);
+        let limits = stock.price_limits().unwrap();
+        assert_eq!(
+            limits,
+            PriceLimits {
+                upper: 91_000,
+                lower: 49_000
+            }
+        );
+
+        // 9,870 * 0.3 = 2,961 -> 상한 12,831 은 내리고, 하한 6,909 는 올린다.
+        let limits = PriceLimits::from_prev_close(&stock, 9_870);
+        assert_eq!(
+            limits,
+            PriceLimits {
+                upper: 12_830,
+                lower: 6_910
+            }
+        );
+
+        // 16,010 * 0.3 = 4,803 -> 상한 20,813 은 50원 단위로 내리고, 하한 11,207 은 10원 단위로 올린다.
+        let limits = PriceLimits::from_prev_close(&stock, 16_010);
+        assert_eq!(
+            limits,
+            PriceLimits {
+                upper: 20_800,
+                lower: 11_210
+            }
+        );
+
+        // 마스터에 상/하한가가 있으면 그대로 쓴다.
+        let listed = Instrument {
+            upper_limit: 90_000,
+            lower_limit: 50_000,
+            ..stock.clone()
+        };
+        assert_eq!(listed.price_limits().unwrap().upper, 90_000);
+
+        assert!(validate_price(&stock, 70_100).is_ok());
+        assert!(validate_price(&stock, 70_050).is_err());
+        assert!(validate_price(&stock, 92_000).is_err());
+        assert!(validate_price(&instrument(InstrumentKind::Stock, 0), 92_000).is_ok());
+    }
+}
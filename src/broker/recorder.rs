@@ -0,0 +1,214 @@
+//! 웹소켓 원본 프레임 녹화.
+//! 받은 프레임을 수신 시각과 함께 gzip JSONL 파일 끝에 덧붙인다.
+//! 프레임을 잠깐 모았다가 (`FLUSH_INTERVAL` 또는 `FLUSH_BYTES`) 묶음마다 gzip 멤버를 따로 닫아서,
+//! 프로세스가 죽어도 그 전 묶음까지는 읽을 수 있다.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::broker::StreamKind;
+
+// 첫 프레임을 받고 이만큼 지나면 묶음을 닫는다.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// 묶음이 이만큼 쌓이면 시간과 상관없이 닫는다.
+const FLUSH_BYTES: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    //수신 시각
+    pub at: DateTime<Utc>,
+    pub stream: StreamKind,
+    //받은 그대로의 텍스트
+    pub frame: String,
+}
+
+pub struct FrameRecorder {
+    tx: Option<Sender<RecordedFrame>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl FrameRecorder {
+    /// `path` 에 이어서 쓴다. 파일이 없으면 만든다.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open frame file {}", path.display()))?;
+
+        let (tx, rx) = mpsc::channel::<RecordedFrame>();
+        // 파일 쓰기가 소켓 수신을 막지 않도록 별도 스레드에서 쓴다.
+        let writer = std::thread::spawn(move || {
+            let mut batch = Vec::new();
+            let mut size = 0;
+            let mut deadline: Option<Instant> = None;
+            loop {
+                let received = match deadline {
+                    Some(deadline) => {
+                        rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                let closed = match received {
+                    Ok(frame) => {
+                        size += frame.frame.len();
+                        deadline.get_or_insert_with(|| Instant::now() + FLUSH_INTERVAL);
+                        batch.push(frame);
+                        if size < FLUSH_BYTES {
+                            continue;
+                        }
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => true,
+                };
+                if !batch.is_empty() {
+                    if let Err(e) = write_batch(&file, &batch) {
+                        error!("Failed to record {} frames: {}", batch.len(), e);
+                    }
+                }
+                if closed {
+                    break;
+                }
+                batch.clear();
+                size = 0;
+                deadline = None;
+            }
+        });
+
+        Ok(Self {
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    /// 지금 받은 프레임을 기록한다.
+    pub fn record(&self, stream: StreamKind, frame: &str) {
+        self.write(RecordedFrame {
+            at: Utc::now(),
+            stream,
+            frame: frame.to_string(),
+        });
+    }
+
+    pub fn write(&self, frame: RecordedFrame) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(frame);
+        }
+    }
+}
+
+impl Drop for FrameRecorder {
+    // 남은 프레임을 다 쓰고 닫는다.
+    fn drop(&mut self) {
+        self.tx.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_batch(file: &File, batch: &[RecordedFrame]) -> Result<()> {
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    for frame in batch {
+        serde_json::to_writer(&mut encoder, frame)?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder.finish()?;
+    Ok(())
+}
+
+/// 녹화 파일을 앞에서부터 한 줄씩 읽는다. 마지막 묶음이 잘려 있으면 거기까지만 읽는다.
+pub struct FrameReader {
+    path: PathBuf,
+    lines: Lines<BufReader<MultiGzDecoder<File>>>,
+}
+
+impl Iterator for FrameReader {
+    type Item = Result<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => {
+                    error!("frame file {} is truncated: {}", self.path.display(), e);
+                    return None;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).context("Failed to parse recorded frame"));
+        }
+    }
+}
+
+/// 녹화 파일을 연다. 프레임은 읽을 때 하나씩 푼다.
+pub fn read_frames(path: impl AsRef<Path>) -> Result<FrameReader> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open frame file {}", path.display()))?;
+    Ok(FrameReader {
+        path: path.to_path_buf(),
+        lines: BufReader::new(MultiGzDecoder::new(file)).lines(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read() {
+        let path = std::env::temp_dir().join(format!("frames-{}.jsonl.gz", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = FrameRecorder::open(&path).unwrap();
+        recorder.record(StreamKind::Tick, r#"{"header":{"tr_cd":"S3_"}}"#);
+        recorder.record(
+            StreamKind::OrderTransaction,
+            r#"{"header":{"tr_cd":"SC0"}}"#,
+        );
+        drop(recorder);
+
+        // 다시 열면 뒤에 이어 쓴다.
+        let recorder = FrameRecorder::open(&path).unwrap();
+        recorder.record(StreamKind::Tick, r#"{"header":{"tr_cd":"K3_"}}"#);
+        drop(recorder);
+
+        let frames: Vec<_> = read_frames(&path).unwrap().collect::<Result<_>>().unwrap();
+        // 한 번 열 때마다 묶음 하나로 쓴다.
+        let members = std::fs::read(&path)
+            .unwrap()
+            .windows(3)
+            .filter(|w| w == &[0x1f, 0x8b, 0x08])
+            .count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(members, 2);
+        let streams: Vec<_> = frames.iter().map(|f| f.stream).collect();
+        assert_eq!(
+            streams,
+            vec![
+                StreamKind::Tick,
+                StreamKind::OrderTransaction,
+                StreamKind::Tick
+            ]
+        );
+        assert_eq!(frames[2].frame, r#"{"header":{"tr_cd":"K3_"}}"#);
+        assert!(frames[0].at <= frames[2].at);
+    }
+}
@@ -0,0 +1,546 @@
+//! 녹화한 웹소켓 프레임을 다시 흘려보내는 브로커.
+//! 체결/호가/주문 이벤트만 재생한다. 주문까지 돌려 보려면 `PaperBroker` 의 feed 로 쓴다.
+//! 체결과 호가는 실시간 등록한 종목만 내보낸다.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::broker::lssec::{parse_order_book, parse_order_result, parse_tick};
+use crate::broker::recorder::{read_frames, RecordedFrame};
+use crate::broker::session::{parse_session, SessionEvent};
+use crate::broker::tradability::{parse_vi, Tradability, TradabilityEvent, TradabilityTracker};
+use crate::broker::{
+    Broker, Candle, CandleInterval, ConnectionEvent, ConnectionState, Instrument, Order,
+    OrderAction, OrderBook, OrderFilter, OrderResult, OrderType, Position, StreamKind, Tick,
+    TimeInForce,
+};
+
+/// 재생 속도
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReplaySpeed {
+    //녹화된 간격 그대로
+    Original,
+    //N 배속
+    Accelerated(f64),
+    //기다리지 않고 바로
+    Unlimited,
+}
+
+impl TryFrom<&str> for ReplaySpeed {
+    type Error = anyhow::Error;
+
+    /// "max" 는 기다리지 않고, 숫자는 그 배속으로 재생한다.
+    fn try_from(speed: &str) -> Result<Self, Self::Error> {
+        match speed.to_lowercase().as_str() {
+            "max" | "unlimited" => Ok(ReplaySpeed::Unlimited),
+            factor => match factor.parse::<f64>() {
+                Ok(factor) if factor > 0.0 => Ok(ReplaySpeed::Accelerated(factor)),
+                _ => Err(anyhow!("Invalid replay speed: {}", speed)),
+            },
+        }
+    }
+}
+
+impl ReplaySpeed {
+    // 첫 프레임부터 `elapsed` 뒤에 받은 프레임을 재생까지 기다릴 시간
+    fn scale(&self, elapsed: chrono::Duration) -> Option<Duration> {
+        let elapsed = elapsed.to_std().unwrap_or_default();
+        match self {
+            ReplaySpeed::Original => Some(elapsed),
+            ReplaySpeed::Accelerated(factor) if *factor > 0.0 => Some(elapsed.div_f64(*factor)),
+            ReplaySpeed::Accelerated(_) | ReplaySpeed::Unlimited => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum FrameSource {
+    Memory(Arc<Vec<RecordedFrame>>),
+    //파일은 재생할 때 앞에서부터 읽는다.
+    File(PathBuf),
+}
+
+impl FrameSource {
+    fn frames(&self) -> Result<Box<dyn Iterator<Item = RecordedFrame> + Send>> {
+        match self {
+            FrameSource::Memory(frames) => {
+                let frames = Arc::clone(frames);
+                Ok(Box::new((0..frames.len()).map(move |i| frames[i].clone())))
+            }
+            FrameSource::File(path) => Ok(Box::new(read_frames(path)?.filter_map(|frame| {
+                frame
+                    .map_err(|e| error!("skip recorded frame: {:#}", e))
+                    .ok()
+            }))),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ReplayBroker {
+    source: FrameSource,
+    //녹화 전체의 첫 프레임 시각. 모든 스트림이 이 시각을 기준으로 간격을 맞춘다.
+    origin: Option<DateTime<Utc>>,
+    //처음 재생을 시작한 시각. 스트림끼리 같이 쓴다.
+    started: Arc<OnceLock<Instant>>,
+    speed: ReplaySpeed,
+    instruments: Arc<HashMap<String, Instrument>>,
+    tick_subscriptions: Arc<Mutex<BTreeSet<String>>>,
+    book_subscriptions: Arc<Mutex<BTreeSet<String>>>,
+    order_books: broadcast::Sender<OrderBook>,
+    events: broadcast::Sender<ConnectionEvent>,
+    sessions: broadcast::Sender<SessionEvent>,
+    tradability: TradabilityTracker,
+    tradability_events: broadcast::Sender<TradabilityEvent>,
+}
+
+impl ReplayBroker {
+    pub fn new(frames: Vec<RecordedFrame>) -> Self {
+        let origin = frames.first().map(|frame| frame.at);
+        Self::with_source(FrameSource::Memory(Arc::new(frames)), origin)
+    }
+
+    /// `FrameRecorder` 로 녹화한 파일을 연다. 프레임은 재생하면서 읽는다.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let origin = read_frames(&path)?
+            .next()
+            .transpose()?
+            .map(|frame| frame.at);
+        Ok(Self::with_source(FrameSource::File(path), origin))
+    }
+
+    fn with_source(source: FrameSource, origin: Option<DateTime<Utc>>) -> Self {
+        Self {
+            source,
+            origin,
+            started: Arc::new(OnceLock::new()),
+            speed: ReplaySpeed::Original,
+            instruments: Arc::new(HashMap::new()),
+            tick_subscriptions: Arc::new(Mutex::new(BTreeSet::new())),
+            book_subscriptions: Arc::new(Mutex::new(BTreeSet::new())),
+            order_books: broadcast::channel(1024).0,
+            events: broadcast::channel(16).0,
+            sessions: broadcast::channel(16).0,
+            tradability: TradabilityTracker::new(),
+            tradability_events: broadcast::channel(256).0,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// 녹화 파일에는 종목 정보가 없으므로 필요하면 따로 넣는다.
+    pub fn with_instruments(mut self, instruments: HashMap<String, Instrument>) -> Self {
+        self.instruments = Arc::new(instruments);
+        self
+    }
+
+    // `kind` 프레임을 녹화된 간격에 맞춰 파싱해서 보낸다. 다 보내면 채널을 닫는다.
+    fn replay<T, P>(&self, kind: StreamKind, token: CancellationToken, parse: P) -> Receiver<T>
+    where
+        T: Send + 'static,
+        P: Fn(&RecordedFrame, &Value) -> Option<T> + Send + 'static,
+    {
+        let source = self.source.clone();
+        let (frame_tx, mut frames) = channel::<RecordedFrame>(100);
+        // 파일 읽기와 압축 풀기는 막히는 작업이라 따로 돌린다.
+        tokio::task::spawn_blocking(move || {
+            let source = match source.frames() {
+                Ok(source) => source,
+                Err(e) => {
+                    error!("Failed to read {:?} frames: {:#}", kind, e);
+                    return;
+                }
+            };
+            for frame in source.filter(|frame| frame.stream == kind) {
+                if frame_tx.blocking_send(frame).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let origin = self.origin;
+        let started = *self.started.get_or_init(Instant::now);
+        let speed = self.speed;
+        let (tx, rx) = channel::<T>(100);
+        tokio::spawn(async move {
+            while let Some(frame) = frames.recv().await {
+                let wait = origin.and_then(|origin| speed.scale(frame.at - origin));
+                if let Some(wait) = wait {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(started + wait) => {}
+                        _ = token.cancelled() => {
+                            info!("receive signal");
+                            return;
+                        }
+                    }
+                }
+                let Ok(json) = serde_json::from_str::<Value>(&frame.frame) else {
+                    continue;
+                };
+                if let Some(item) = parse(&frame, &json) {
+                    if tx.send(item).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            info!("{:?} replay finished", kind);
+        });
+        rx
+    }
+
+    fn notify_connected(&self, stream: StreamKind) {
+        let _ = self.events.send(ConnectionEvent {
+            stream,
+            state: ConnectionState::Connected,
+            at: chrono::Utc::now(),
+        });
+    }
+}
+
+fn unsupported<T>(name: &str) -> Result<T> {
+    Err(anyhow!("{} is not available in replay", name))
+}
+
+#[async_trait]
+impl Broker for ReplayBroker {
+    async fn get_instruments(&self) -> Result<HashMap<String, Instrument>> {
+        Ok(self.instruments.as_ref().clone())
+    }
+
+    async fn subscribe(&self, ticker: &str) -> Result<()> {
+        self.tick_subscriptions
+            .lock()
+            .unwrap()
+            .insert(ticker.to_string());
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, ticker: &str) -> Result<()> {
+        self.tick_subscriptions.lock().unwrap().remove(ticker);
+        Ok(())
+    }
+
+    async fn subscriptions(&self) -> Vec<String> {
+        self.tick_subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    async fn subscribe_order_book(&self, ticker: &str) -> Result<()> {
+        self.book_subscriptions
+            .lock()
+            .unwrap()
+            .insert(ticker.to_string());
+        Ok(())
+    }
+
+    async fn unsubscribe_order_book(&self, ticker: &str) -> Result<()> {
+        self.book_subscriptions.lock().unwrap().remove(ticker);
+        Ok(())
+    }
+
+    fn order_book_stream(&self) -> broadcast::Receiver<OrderBook> {
+        self.order_books.subscribe()
+    }
+
+    async fn get_balance(&self) -> Result<i64> {
+        unsupported("get_balance")
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>> {
+        unsupported("get_positions")
+    }
+
+    async fn get_candles(
+        &self,
+        _ticker: &str,
+        _interval: CandleInterval,
+        _from: NaiveDate,
+        _to: NaiveDate,
+    ) -> Result<Vec<Candle>> {
+        unsupported("get_candles")
+    }
+
+    async fn order_cancel(&self, _order: Order) -> Result<()> {
+        unsupported("order_cancel")
+    }
+
+    async fn get_orders(&self, _filter: OrderFilter) -> Result<Vec<Order>> {
+        unsupported("get_orders")
+    }
+
+    async fn get_executions(&self, _date: NaiveDate) -> Result<Vec<Order>> {
+        unsupported("get_executions")
+    }
+
+    async fn order_modify(&self, _order: Order, _new_qty: i64, _new_price: i64) -> Result<Order> {
+        unsupported("order_modify")
+    }
+
+    async fn get_access_token(&self) -> Result<String> {
+        unsupported("get_access_token")
+    }
+
+    async fn connect_websocket(&self, token: CancellationToken) -> Result<Receiver<Tick>> {
+        self.notify_connected(StreamKind::Tick);
+        let order_books = self.order_books.clone();
+        let sessions = self.sessions.clone();
+        let tradability = self.tradability.clone();
+        let tradability_events = self.tradability_events.clone();
+        let tick_subscriptions = Arc::clone(&self.tick_subscriptions);
+        let book_subscriptions = Arc::clone(&self.book_subscriptions);
+        // 받은 시각은 녹화 당시 시각으로 되돌린다.
+        let parse = move |frame: &RecordedFrame, json: &Value| {
+            if let Some(mut book) = parse_order_book(json) {
+                if book_subscriptions.lock().unwrap().contains(&book.ticker) {
+                    book.received_at = frame.at;
+                    let _ = order_books.send(book);
+                }
+                return None;
+            }
+            if let Some(mut event) = parse_session(json) {
+                event.at = frame.at;
+                let _ = sessions.send(event);
+                return None;
+            }
+            if let Some(mut event) = parse_vi(json) {
+                event.at = frame.at;
+                tradability.apply(&event);
+                let _ = tradability_events.send(event);
+                return None;
+            }
+            let mut tick = parse_tick(json)?;
+            if !tick_subscriptions.lock().unwrap().contains(&tick.ticker) {
+                return None;
+            }
+            tick.received_at = frame.at;
+            Some(tick)
+        };
+        Ok(self.replay(StreamKind::Tick, token, parse))
+    }
+
+    async fn order(
+        &self,
+        _ticker: &str,
+        _amount: i64,
+        _price: i64,
+        _order_action: OrderAction,
+        _order_type: OrderType,
+        _time_in_force: TimeInForce,
+    ) -> Result<Order> {
+        unsupported("order")
+    }
+
+    async fn connect_websocket_order_transaction(
+        &self,
+        token: CancellationToken,
+    ) -> Result<Receiver<OrderResult>> {
+        self.notify_connected(StreamKind::OrderTransaction);
+        Ok(self.replay(StreamKind::OrderTransaction, token, |_, json| {
+            parse_order_result(json)
+        }))
+    }
+
+    fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    fn session_events(&self) -> broadcast::Receiver<SessionEvent> {
+        self.sessions.subscribe()
+    }
+
+    fn tradability(&self, ticker: &str) -> Tradability {
+        self.tradability.get(ticker)
+    }
+
+    fn tradability_events(&self) -> broadcast::Receiver<TradabilityEvent> {
+        self.tradability_events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::broker::OrderResultType;
+    use serde_json::json;
+
+    fn frame(millis: i64, stream: StreamKind, body: Value) -> RecordedFrame {
+        let start = chrono::DateTime::parse_from_rfc3339("2026-10-16T00:30:00Z").unwrap();
+        RecordedFrame {
+            at: start.to_utc() + chrono::Duration::milliseconds(millis),
+            stream,
+            frame: body.to_string(),
+        }
+    }
+
+    fn tick(price: i64) -> Value {
+        ticker_tick("005930", price)
+    }
+
+    fn ticker_tick(ticker: &str, price: i64) -> Value {
+        json!({
+            "header": {"tr_cd": "S3_", "tr_key": ticker},
+            "body": {
+                "chetime": "093000", "sign": "2", "change": "100", "price": price.to_string(),
+                "open": "70000", "high": "70500", "low": "69900", "cgubun": "+",
+                "cvolume": "1", "volume": "100", "shcode": ticker
+            }
+        })
+    }
+
+    fn frames() -> Vec<RecordedFrame> {
+        vec![
+            frame(0, StreamKind::Tick, tick(70000)),
+            frame(
+                100,
+                StreamKind::OrderTransaction,
+                json!({"header": {"tr_cd": "SC0"}, "body": {"ordno": "0000000001", "shtnIsuno": "A005930"}}),
+            ),
+            frame(400, StreamKind::Tick, tick(70100)),
+        ]
+    }
+
+    async fn replay_ticks(speed: ReplaySpeed) -> (Vec<Tick>, Duration) {
+        let broker = ReplayBroker::new(frames()).with_speed(speed);
+        broker.subscribe("005930").await.unwrap();
+        let started = Instant::now();
+        let mut rx = broker
+            .connect_websocket(CancellationToken::new())
+            .await
+            .unwrap();
+        let mut ticks = Vec::new();
+        while let Some(tick) = rx.recv().await {
+            ticks.push(tick);
+        }
+        (ticks, started.elapsed())
+    }
+
+    #[tokio::test]
+    async fn test_replay_speed() {
+        let (ticks, elapsed) = replay_ticks(ReplaySpeed::Original).await;
+        assert_eq!(
+            ticks.iter().map(|t| t.price).collect::<Vec<_>>(),
+            vec![70000, 70100]
+        );
+        assert_eq!(
+            ticks[1].received_at - ticks[0].received_at,
+            chrono::Duration::milliseconds(400)
+        );
+        assert!(elapsed >= Duration::from_millis(400));
+
+        let (ticks, elapsed) = replay_ticks(ReplaySpeed::Accelerated(4.0)).await;
+        assert_eq!(ticks.len(), 2);
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(400));
+
+        let (ticks, elapsed) = replay_ticks(ReplaySpeed::Unlimited).await;
+        assert_eq!(ticks.len(), 2);
+        assert!(elapsed < Duration::from_millis(100));
+
+        assert_eq!(
+            ReplaySpeed::try_from("max").unwrap(),
+            ReplaySpeed::Unlimited
+        );
+        assert_eq!(
+            ReplaySpeed::try_from("10").unwrap(),
+            ReplaySpeed::Accelerated(10.0)
+        );
+        assert!(ReplaySpeed::try_from("0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_subscriptions() {
+        let mut frames = frames();
+        frames.push(frame(500, StreamKind::Tick, ticker_tick("000660", 180000)));
+        let broker = ReplayBroker::new(frames).with_speed(ReplaySpeed::Unlimited);
+        // 등록한 종목의 체결만 나온다.
+        broker.subscribe("000660").await.unwrap();
+        let mut rx = broker
+            .connect_websocket(CancellationToken::new())
+            .await
+            .unwrap();
+        let mut tickers = Vec::new();
+        while let Some(tick) = rx.recv().await {
+            tickers.push(tick.ticker);
+        }
+        assert_eq!(tickers, vec!["000660"]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_order_transaction() {
+        let broker = ReplayBroker::new(frames()).with_speed(ReplaySpeed::Unlimited);
+        let mut rx = broker
+            .connect_websocket_order_transaction(CancellationToken::new())
+            .await
+            .unwrap();
+        let result = rx.recv().await.unwrap();
+        assert_eq!(result.id, "1");
+        assert_eq!(result.result, OrderResultType::Wait);
+        assert_eq!(result.ticker.as_deref(), Some("005930"));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replay_shared_origin() {
+        // 주문 이벤트도 녹화 전체의 첫 프레임 (체결) 기준으로 100ms 뒤에 나온다.
+        let broker = ReplayBroker::new(frames());
+        broker.subscribe("005930").await.unwrap();
+        let started = Instant::now();
+        let mut ticks = broker
+            .connect_websocket(CancellationToken::new())
+            .await
+            .unwrap();
+        let mut results = broker
+            .connect_websocket_order_transaction(CancellationToken::new())
+            .await
+            .unwrap();
+        results.recv().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        ticks.recv().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_file() {
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl.gz", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let recorder = crate::broker::recorder::FrameRecorder::open(&path).unwrap();
+        for frame in frames() {
+            recorder.write(frame);
+        }
+        drop(recorder);
+
+        let broker = ReplayBroker::open(&path)
+            .unwrap()
+            .with_speed(ReplaySpeed::Unlimited);
+        broker.subscribe("005930").await.unwrap();
+        let mut rx = broker
+            .connect_websocket(CancellationToken::new())
+            .await
+            .unwrap();
+        let mut prices = Vec::new();
+        while let Some(tick) = rx.recv().await {
+            prices.push(tick.price);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(prices, vec![70000, 70100]);
+    }
+}
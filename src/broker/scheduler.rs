@@ -0,0 +1,301 @@
+//! LS REST 호출 스케줄러.
+//! tr_cd 별 호출 제한을 지키도록 요청을 줄 세우고, 주문 TR 을 조회 TR 보다 먼저 내보낸다.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+/// `per` 동안 최대 `calls` 번 호출할 수 있다.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub calls: usize,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn per_second(calls: usize) -> Self {
+        Self {
+            calls,
+            per: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Query,
+    Order,
+}
+
+impl Priority {
+    pub fn of(tr_cd: &str) -> Self {
+        if tr_cd.starts_with("CSPAT") {
+            Priority::Order
+        } else {
+            Priority::Query
+        }
+    }
+}
+
+// 높은 우선순위 먼저, 같은 우선순위는 들어온 순서대로
+type Ticket = (Reverse<Priority>, u64);
+
+#[derive(Default)]
+struct State {
+    next_seq: u64,
+    waiting: BTreeMap<Ticket, String>,
+    windows: HashMap<String, VecDeque<Instant>>,
+    global: VecDeque<Instant>,
+}
+
+pub struct Scheduler {
+    limits: HashMap<String, RateLimit>,
+    default_limit: RateLimit,
+    global_limit: Option<RateLimit>,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+impl Default for Scheduler {
+    /// LS 개발자센터에 공지된 TR 별 초당 전송 제한
+    fn default() -> Self {
+        let limits = [
+            ("CSPAT00601", 10),
+            ("CSPAT00701", 3),
+            ("CSPAT00801", 3),
+            ("CSPAQ12200", 1),
+            ("t0424", 1),
+            ("t0425", 1),
+            ("t8436", 2),
+            ("t8431", 1),
+            ("t1102", 10),
+            ("t1405", 1),
+            ("CSPAQ13700", 1),
+        ];
+        Self::new(RateLimit::per_second(1)).with_limits(
+            limits
+                .into_iter()
+                .map(|(tr_cd, calls)| (tr_cd.to_string(), RateLimit::per_second(calls))),
+        )
+    }
+}
+
+impl Scheduler {
+    pub fn new(default_limit: RateLimit) -> Self {
+        Self {
+            limits: HashMap::new(),
+            default_limit,
+            global_limit: None,
+            state: Mutex::new(State::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn with_limit(mut self, tr_cd: &str, limit: RateLimit) -> Self {
+        self.limits.insert(tr_cd.to_string(), limit);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: impl IntoIterator<Item = (String, RateLimit)>) -> Self {
+        self.limits.extend(limits);
+        self
+    }
+
+    /// 모든 TR 을 합친 호출 제한. 여기가 막힐 때 주문이 조회보다 먼저 나간다.
+    pub fn with_global_limit(mut self, limit: RateLimit) -> Self {
+        self.global_limit = Some(limit);
+        self
+    }
+
+    pub fn limit(&self, tr_cd: &str) -> RateLimit {
+        self.limits
+            .get(tr_cd)
+            .copied()
+            .unwrap_or(self.default_limit)
+    }
+
+    /// 호출 차례가 올 때까지 기다리고, 대기한 시간을 돌려준다.
+    pub async fn acquire(&self, tr_cd: &str) -> Duration {
+        let started = Instant::now();
+        let ticket = {
+            let mut state = self.state.lock().unwrap();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            let ticket = (Reverse(Priority::of(tr_cd)), seq);
+            state.waiting.insert(ticket, tr_cd.to_string());
+            ticket
+        };
+        // 기다리다 취소되면 줄에서 빠지도록
+        let mut guard = WaitGuard {
+            scheduler: self,
+            ticket: Some(ticket),
+        };
+
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let wake_at = match self.try_grant(ticket) {
+                Ok(()) => {
+                    guard.ticket = None;
+                    self.notify.notify_waiters();
+                    return started.elapsed();
+                }
+                Err(wake_at) => wake_at,
+            };
+            match wake_at {
+                Some(at) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(at.into()) => {}
+                        _ = notified => {}
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    // 차례가 아니면 다음에 다시 확인할 시각을 돌려준다.
+    fn try_grant(&self, ticket: Ticket) -> Result<(), Option<Instant>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let mut wake_at = None;
+        if let Some(limit) = self.global_limit {
+            if let Some(at) = next_slot(&mut state.global, limit, now) {
+                return Err(Some(at));
+            }
+        }
+
+        // 창이 꽉 찬 TR 의 요청은 건너뛰어 다른 TR 을 막지 않는다.
+        let mut granted = None;
+        for (waiting, tr_cd) in &state.waiting {
+            let window = state.windows.entry(tr_cd.clone()).or_default();
+            match next_slot(window, self.limit(tr_cd), now) {
+                Some(at) => wake_at = Some(wake_at.map_or(at, |w: Instant| w.min(at))),
+                None if *waiting == ticket => {
+                    window.push_back(now);
+                    granted = Some(ticket);
+                    break;
+                }
+                None => return Err(wake_at),
+            }
+        }
+
+        let ticket = granted.ok_or(wake_at)?;
+        state.waiting.remove(&ticket);
+        if self.global_limit.is_some() {
+            state.global.push_back(now);
+        }
+        Ok(())
+    }
+
+    fn cancel(&self, ticket: Ticket) {
+        self.state.lock().unwrap().waiting.remove(&ticket);
+        self.notify.notify_waiters();
+    }
+}
+
+// 창에 자리가 있으면 None, 없으면 자리가 나는 시각
+fn next_slot(window: &mut VecDeque<Instant>, limit: RateLimit, now: Instant) -> Option<Instant> {
+    while window
+        .front()
+        .is_some_and(|sent| now.duration_since(*sent) >= limit.per)
+    {
+        window.pop_front();
+    }
+    if window.len() < limit.calls {
+        None
+    } else {
+        window.front().map(|sent| *sent + limit.per)
+    }
+}
+
+struct WaitGuard<'a> {
+    scheduler: &'a Scheduler,
+    ticket: Option<Ticket>,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            self.scheduler.cancel(ticket);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_rate_limit_per_tr() {
+        let scheduler = Arc::new(Scheduler::new(RateLimit::per_second(10)).with_limit(
+            "t0424",
+            RateLimit {
+                calls: 2,
+                per: Duration::from_millis(200),
+            },
+        ));
+
+        let started = Instant::now();
+        for _ in 0..4 {
+            scheduler.acquire("t0424").await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // 다른 TR 은 기다리지 않는다.
+        let wait = scheduler.acquire("CSPAQ12200").await;
+        assert!(wait < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_order_priority() {
+        let scheduler = Arc::new(
+            Scheduler::new(RateLimit::per_second(100)).with_global_limit(RateLimit {
+                calls: 1,
+                per: Duration::from_millis(100),
+            }),
+        );
+        scheduler.acquire("t0424").await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for tr_cd in ["t0424", "CSPAQ12200", "CSPAT00601"] {
+            let scheduler = Arc::clone(&scheduler);
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let wait = scheduler.acquire(tr_cd).await;
+                tx.send((tr_cd, wait)).unwrap();
+            });
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        drop(tx);
+
+        let mut order = Vec::new();
+        while let Some((tr_cd, wait)) = rx.recv().await {
+            assert!(wait > Duration::ZERO);
+            order.push(tr_cd);
+        }
+        assert_eq!(order, vec!["CSPAT00601", "t0424", "CSPAQ12200"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_leaves_queue() {
+        let scheduler = Scheduler::new(RateLimit {
+            calls: 1,
+            per: Duration::from_millis(100),
+        });
+        scheduler.acquire("t0424").await;
+
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(20), scheduler.acquire("t0424")).await;
+        assert!(cancelled.is_err());
+        assert!(scheduler.state.lock().unwrap().waiting.is_empty());
+    }
+}
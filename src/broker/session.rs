@@ -0,0 +1,219 @@
+//! 장 운영 상태.
+//! LS 실시간 JIF(장운영정보) 로 장전 동시호가, 정규장, 장마감 동시호가, 시간외, 거래정지를 구분한다.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use serde_json::Value;
+
+use crate::broker::calendar::TradingCalendar;
+use crate::broker::{Market, OrderType, TimeInForce};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SessionPhase {
+    //장 종료, 휴장
+    Closed,
+    //장전 동시호가 (08:30 ~ 09:00)
+    PreOpenAuction,
+    //정규장 접속매매 (09:00 ~ 15:20)
+    Continuous,
+    //장마감 동시호가 (15:20 ~ 15:30)
+    ClosingAuction,
+    //시간외 (15:40 ~ 18:00)
+    AfterHours,
+    //서킷브레이커 등으로 매매 정지
+    Halted,
+}
+
+impl SessionPhase {
+    /// JIF 장상태(jstatus). 예고(장개시 N분전 등)처럼 상태가 바뀌지 않는 코드는 None.
+    pub fn from_jstatus(code: &str) -> Option<Self> {
+        match code {
+            "11" => Some(SessionPhase::PreOpenAuction),
+            "21" => Some(SessionPhase::Continuous),
+            "31" => Some(SessionPhase::ClosingAuction),
+            "41" => Some(SessionPhase::Closed),
+            // 시간외종가, 시간외단일가 개시
+            "51" | "53" => Some(SessionPhase::AfterHours),
+            "54" => Some(SessionPhase::Closed),
+            // 서킷브레이커 발동 / 해제 / 재개 동시호가
+            "61" => Some(SessionPhase::Halted),
+            "62" => Some(SessionPhase::Continuous),
+            "63" => Some(SessionPhase::PreOpenAuction),
+            _ => None,
+        }
+    }
+
+    /// 이 상태에서 낼 수 있는 주문인지
+    pub fn accepts(&self, order_type: OrderType, time_in_force: TimeInForce) -> bool {
+        let day = time_in_force == TimeInForce::Day;
+        match self {
+            SessionPhase::Continuous => {
+                !matches!(order_type, OrderType::PreOpen | OrderType::AfterHoursSingle)
+            }
+            SessionPhase::PreOpenAuction => {
+                day && matches!(
+                    order_type,
+                    OrderType::Limit
+                        | OrderType::Market
+                        | OrderType::ConditionalLimit
+                        | OrderType::PreOpen
+                )
+            }
+            SessionPhase::ClosingAuction => {
+                day && matches!(order_type, OrderType::Limit | OrderType::Market)
+            }
+            SessionPhase::AfterHours => day && order_type == OrderType::AfterHoursSingle,
+            SessionPhase::Closed | SessionPhase::Halted => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionEvent {
+    pub market: Market,
+    pub phase: SessionPhase,
+    //JIF jstatus 원본
+    pub code: String,
+    pub at: DateTime<Utc>,
+}
+
+pub(crate) fn parse_session(json: &Value) -> Option<SessionEvent> {
+    if json.get("header")?.get("tr_cd")?.as_str()? != "JIF" {
+        return None;
+    }
+    let body = json.get("body")?;
+    // 1 코스피, 2 코스닥. 선물/옵션 등 나머지 장구분은 쓰지 않는다.
+    let market = match body.get("jangubun")?.as_str()? {
+        "1" => Market::KOSPI,
+        "2" => Market::KOSDAQ,
+        _ => return None,
+    };
+    let code = body.get("jstatus")?.as_str()?;
+    Some(SessionEvent {
+        market,
+        phase: SessionPhase::from_jstatus(code)?,
+        code: code.to_string(),
+        at: Utc::now(),
+    })
+}
+
+/// 한국 시간 (KST)
+pub fn kst_now() -> NaiveDateTime {
+    let kst = FixedOffset::east_opt(9 * 3600).unwrap();
+    Utc::now().with_timezone(&kst).naive_local()
+}
+
+/// 시장별 현재 장 상태.
+/// JIF 는 상태가 바뀔 때만 오므로 받기 전까지는 달력으로 추정한다.
+#[derive(Clone, Default)]
+pub struct SessionTracker {
+    phases: Arc<RwLock<HashMap<Market, SessionPhase>>>,
+    calendar: Arc<TradingCalendar>,
+}
+
+impl SessionTracker {
+    pub fn new() -> Self {
+        Self::default().with_calendar(TradingCalendar::krx())
+    }
+
+    pub fn with_calendar(mut self, calendar: TradingCalendar) -> Self {
+        self.calendar = Arc::new(calendar);
+        self
+    }
+
+    /// 이벤트를 반영하고 이전 상태를 돌려준다.
+    pub fn apply(&self, event: &SessionEvent) -> SessionPhase {
+        let previous = self.phase_at(event.market, kst_now());
+        self.phases
+            .write()
+            .unwrap()
+            .insert(event.market, event.phase);
+        previous
+    }
+
+    pub fn phase(&self, market: Market) -> SessionPhase {
+        self.phase_at(market, kst_now())
+    }
+
+    fn phase_at(&self, market: Market, now: NaiveDateTime) -> SessionPhase {
+        // JIF 를 아직 받지 못한 시장은 달력을 따른다.
+        self.phases
+            .read()
+            .unwrap()
+            .get(&market)
+            .copied()
+            .unwrap_or_else(|| self.calendar.phase(now))
+    }
+}
+
+/// 장 시작 (동시호가/휴장에서 정규장으로)
+pub fn is_open_transition(previous: SessionPhase, current: SessionPhase) -> bool {
+    current == SessionPhase::Continuous
+        && matches!(
+            previous,
+            SessionPhase::Closed | SessionPhase::PreOpenAuction
+        )
+}
+
+/// 장 마감 (정규장/마감 동시호가에서 종료로)
+pub fn is_close_transition(previous: SessionPhase, current: SessionPhase) -> bool {
+    current == SessionPhase::Closed
+        && matches!(
+            previous,
+            SessionPhase::Continuous | SessionPhase::ClosingAuction
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_accepts() {
+        use OrderType::*;
+        let day = TimeInForce::Day;
+        assert!(SessionPhase::Continuous.accepts(Limit, TimeInForce::Ioc));
+        assert!(!SessionPhase::Continuous.accepts(AfterHoursSingle, day));
+        assert!(SessionPhase::PreOpenAuction.accepts(Limit, day));
+        assert!(!SessionPhase::PreOpenAuction.accepts(BestLimit, day));
+        assert!(!SessionPhase::ClosingAuction.accepts(Limit, TimeInForce::Fok));
+        assert!(SessionPhase::AfterHours.accepts(AfterHoursSingle, day));
+        assert!(!SessionPhase::AfterHours.accepts(Limit, day));
+        assert!(!SessionPhase::Halted.accepts(Market, day));
+        assert!(!SessionPhase::Closed.accepts(Limit, day));
+    }
+
+    #[test]
+    fn test_parse_session() {
+        let frame = json!({
+            "header": {"tr_cd": "JIF", "tr_key": "0"},
+            "body": {"jangubun": "2", "jstatus": "21"}
+        });
+        let event = parse_session(&frame).unwrap();
+        assert_eq!(event.market, Market::KOSDAQ);
+        assert_eq!(event.phase, SessionPhase::Continuous);
+
+        // 장개시 1분전 예고는 상태가 아니다.
+        let notice = json!({
+            "header": {"tr_cd": "JIF", "tr_key": "0"},
+            "body": {"jangubun": "1", "jstatus": "23"}
+        });
+        assert!(parse_session(&notice).is_none());
+
+        let tracker = SessionTracker::new();
+        let previous = tracker.apply(&event);
+        assert_eq!(tracker.phase(Market::KOSDAQ), SessionPhase::Continuous);
+        assert!(!is_close_transition(previous, SessionPhase::Continuous));
+        assert!(is_open_transition(
+            SessionPhase::PreOpenAuction,
+            SessionPhase::Continuous
+        ));
+        assert!(is_close_transition(
+            SessionPhase::ClosingAuction,
+            SessionPhase::Closed
+        ));
+    }
+}
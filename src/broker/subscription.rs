@@ -0,0 +1,249 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::broker::Broker;
+
+/// 실시간 등록 종류
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feed {
+    Tick,
+    OrderBook,
+}
+
+/// 종목별 실시간 등록을 참조 카운트로 관리한다.
+/// 여러 전략이 같은 종목을 공유해도 처음 필요할 때 한 번 등록하고, 마지막 참조가 사라질 때 해제한다.
+pub struct SubscriptionManager {
+    client: Arc<dyn Broker>,
+    feed: Feed,
+    counts: Mutex<HashMap<String, usize>>,
+    // 전략 등 소유자별로 잡고 있는 종목
+    owners: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl SubscriptionManager {
+    pub fn new(client: Arc<dyn Broker>) -> Self {
+        Self {
+            client,
+            feed: Feed::Tick,
+            counts: Mutex::new(HashMap::new()),
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 체결 대신 `feed` 를 등록한다.
+    pub fn with_feed(mut self, feed: Feed) -> Self {
+        self.feed = feed;
+        self
+    }
+
+    /// `owner` 가 잡고 있는 종목을 `targets` 로 맞춘다. 새로 생긴 종목만 등록하고 빠진 종목만 해제한다.
+    /// 실패한 종목은 건너뛰고 나머지를 마저 맞춘 뒤, 실패한 종목을 모아 에러로 돌려준다.
+    /// 실패한 종목은 기억하지 않으므로 (해제 실패는 계속 잡고 있으므로) 다음 호출에서 다시 시도한다.
+    pub async fn set_targets(&self, owner: &str, targets: &HashSet<String>) -> Result<()> {
+        let mut owned = self.owners.lock().await.remove(owner).unwrap_or_default();
+        let added: Vec<String> = targets.difference(&owned).cloned().collect();
+        let removed: Vec<String> = owned.difference(targets).cloned().collect();
+
+        let mut failed = Vec::new();
+        for ticker in added {
+            match self.acquire(&ticker).await {
+                Ok(()) => {
+                    owned.insert(ticker);
+                }
+                Err(e) => {
+                    error!("{} failed to subscribe {}: {:#}", owner, ticker, e);
+                    failed.push(format!("subscribe {}: {:#}", ticker, e));
+                }
+            }
+        }
+        for ticker in removed {
+            match self.release(&ticker).await {
+                Ok(()) => {
+                    owned.remove(&ticker);
+                }
+                Err(e) => {
+                    error!("{} failed to unsubscribe {}: {:#}", owner, ticker, e);
+                    failed.push(format!("unsubscribe {}: {:#}", ticker, e));
+                }
+            }
+        }
+
+        if !owned.is_empty() {
+            self.owners.lock().await.insert(owner.to_string(), owned);
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{} subscription failed ({})",
+                owner,
+                failed.join(", ")
+            ))
+        }
+    }
+
+    pub async fn targets(&self, owner: &str) -> HashSet<String> {
+        self.owners
+            .lock()
+            .await
+            .get(owner)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn acquire(&self, ticker: &str) -> Result<()> {
+        let mut counts = self.counts.lock().await;
+        let count = counts.get(ticker).copied().unwrap_or(0);
+        if count == 0 {
+            match self.feed {
+                Feed::Tick => self.client.subscribe(ticker).await?,
+                Feed::OrderBook => self.client.subscribe_order_book(ticker).await?,
+            }
+        }
+        counts.insert(ticker.to_string(), count + 1);
+        Ok(())
+    }
+
+    pub async fn release(&self, ticker: &str) -> Result<()> {
+        let mut counts = self.counts.lock().await;
+        match counts.get_mut(ticker) {
+            None => Err(anyhow!("{} is not subscribed", ticker)),
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                Ok(())
+            }
+            Some(_) => {
+                match self.feed {
+                    Feed::Tick => self.client.unsubscribe(ticker).await?,
+                    Feed::OrderBook => self.client.unsubscribe_order_book(ticker).await?,
+                }
+                counts.remove(ticker);
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn ref_count(&self, ticker: &str) -> usize {
+        self.counts.lock().await.get(ticker).copied().unwrap_or(0)
+    }
+
+    pub async fn tickers(&self) -> Vec<String> {
+        self.counts.lock().await.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::broker::lssec::LsSecClient;
+    use crate::broker::mock::MockLsServer;
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn test_shared_subscription() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment());
+        let tk = CancellationToken::new();
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        let manager = SubscriptionManager::new(Arc::new(client));
+
+        manager.acquire("005930").await.unwrap();
+        manager.acquire("005930").await.unwrap();
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+        assert_eq!(manager.ref_count("005930").await, 2);
+
+        manager.release("005930").await.unwrap();
+        assert_eq!(manager.ref_count("005930").await, 1);
+        assert!(server
+            .subscriptions()
+            .await
+            .contains(&("S3_".to_string(), "005930".to_string())));
+
+        manager.release("005930").await.unwrap();
+        server.wait_unsubscribed("S3_", "005930").await.unwrap();
+        assert!(manager.tickers().await.is_empty());
+        assert!(manager.release("005930").await.is_err());
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_set_targets() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment());
+        let tk = CancellationToken::new();
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        let manager = SubscriptionManager::new(Arc::new(client));
+        let set = |tickers: &[&str]| -> HashSet<String> {
+            tickers.iter().map(|t| t.to_string()).collect()
+        };
+
+        manager
+            .set_targets("envelope", &set(&["005930", "092190"]))
+            .await
+            .unwrap();
+        manager
+            .set_targets("sample", &set(&["005930"]))
+            .await
+            .unwrap();
+        server.wait_subscribed("K3_", "092190").await.unwrap();
+        assert_eq!(manager.ref_count("005930").await, 2);
+
+        // 빠진 종목만 해제하고 다른 전략이 쓰는 종목은 남긴다.
+        manager
+            .set_targets("envelope", &set(&["030520"]))
+            .await
+            .unwrap();
+        server.wait_subscribed("K3_", "030520").await.unwrap();
+        server.wait_unsubscribed("K3_", "092190").await.unwrap();
+        assert_eq!(manager.ref_count("005930").await, 1);
+        assert_eq!(manager.targets("envelope").await, set(&["030520"]));
+
+        manager
+            .set_targets("sample", &HashSet::new())
+            .await
+            .unwrap();
+        server.wait_unsubscribed("S3_", "005930").await.unwrap();
+        assert!(manager.targets("sample").await.is_empty());
+
+        // 모르는 종목은 등록에 실패하고 기억하지 않는다. 나머지 종목은 마저 등록한다.
+        let err = manager
+            .set_targets("sample", &set(&["000000", "005930"]))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("000000"));
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+        assert_eq!(manager.targets("sample").await, set(&["005930"]));
+        tk.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_order_book_targets() {
+        let server = MockLsServer::start().await.unwrap();
+        let client = LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment());
+        let tk = CancellationToken::new();
+        let _sockets = client.connect_websocket(tk.clone()).await.unwrap();
+        let manager = SubscriptionManager::new(Arc::new(client)).with_feed(Feed::OrderBook);
+        let targets: HashSet<String> = ["005930".to_string()].into();
+
+        manager.set_targets("envelope", &targets).await.unwrap();
+        server.wait_subscribed("H1_", "005930").await.unwrap();
+        assert!(!server
+            .subscriptions()
+            .await
+            .contains(&("S3_".to_string(), "005930".to_string())));
+
+        manager
+            .set_targets("envelope", &HashSet::new())
+            .await
+            .unwrap();
+        server.wait_unsubscribed("H1_", "005930").await.unwrap();
+        tk.cancel();
+    }
+}
@@ -0,0 +1,272 @@
+//! 종목별 매매 가능 상태.
+//! LS 실시간 VI_(VI발동해제) 와 t1405(매매정지 종목) 로 변동성완화장치 단일가, 거래정지를 구분한다.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::broker::{OrderType, TimeInForce};
+
+// VI 는 2분 뒤 (길게는 30초 더) 풀린다. 해제 이벤트를 놓쳐도 이 시간이 지나면 풀린 것으로 본다.
+const VI_TIMEOUT: Duration = Duration::from_secs(180);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ViKind {
+    //정적 VI (기준가 대비 단일 호가 급변)
+    Static,
+    //동적 VI (직전 체결가 대비 급변)
+    Dynamic,
+    //정적, 동적 동시 발동
+    Both,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Tradability {
+    #[default]
+    Normal,
+    //VI 발동, 2분간 단일가매매
+    Vi {
+        kind: ViKind,
+        //VI 발동가격
+        trigger_price: i64,
+    },
+    //매매정지
+    Halted,
+}
+
+impl Tradability {
+    /// VI 단일가 동안에는 조건 없는 지정가, 시장가만 받는다.
+    pub fn accepts(&self, order_type: OrderType, time_in_force: TimeInForce) -> bool {
+        match self {
+            Tradability::Normal => true,
+            Tradability::Vi { .. } => {
+                time_in_force == TimeInForce::Day
+                    && matches!(order_type, OrderType::Limit | OrderType::Market)
+            }
+            Tradability::Halted => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TradabilityEvent {
+    pub ticker: String,
+    pub tradability: Tradability,
+    pub at: DateTime<Utc>,
+}
+
+fn price(body: &Value, field: &str) -> i64 {
+    body.get(field)
+        .and_then(|v| match v {
+            Value::String(s) => s.trim().parse().ok(),
+            v => v.as_i64(),
+        })
+        .unwrap_or(0)
+}
+
+pub(crate) fn parse_vi(json: &Value) -> Option<TradabilityEvent> {
+    if json.get("header")?.get("tr_cd")?.as_str()? != "VI_" {
+        return None;
+    }
+    let body = json.get("body")?;
+    let ticker = body.get("shcode")?.as_str()?.trim();
+    let kind = match body.get("vi_gubun")?.as_str()? {
+        "0" => None,
+        "1" => Some(ViKind::Static),
+        "2" => Some(ViKind::Dynamic),
+        "3" => Some(ViKind::Both),
+        _ => return None,
+    };
+    let tradability = match kind {
+        Some(kind) => Tradability::Vi {
+            kind,
+            trigger_price: price(body, "vi_trgprice"),
+        },
+        None => Tradability::Normal,
+    };
+    Some(TradabilityEvent {
+        ticker: ticker.to_string(),
+        tradability,
+        at: Utc::now(),
+    })
+}
+
+/// 종목별 현재 매매 가능 상태. 기록이 없으면 `Normal`.
+#[derive(Clone, Default)]
+pub struct TradabilityTracker {
+    //상태와 그 상태가 된 시각
+    states: Arc<RwLock<HashMap<String, (Tradability, Instant)>>>,
+}
+
+impl TradabilityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 이벤트를 반영하고 이전 상태를 돌려준다.
+    pub fn apply(&self, event: &TradabilityEvent) -> Tradability {
+        self.apply_at(event, Instant::now())
+    }
+
+    fn apply_at(&self, event: &TradabilityEvent, now: Instant) -> Tradability {
+        let mut states = self.states.write().unwrap();
+        let previous = match event.tradability {
+            Tradability::Normal => states.remove(&event.ticker),
+            tradability => states.insert(event.ticker.clone(), (tradability, now)),
+        };
+        previous.map_or(Tradability::Normal, |state| current(state, now))
+    }
+
+    pub fn get(&self, ticker: &str) -> Tradability {
+        self.get_at(ticker, Instant::now())
+    }
+
+    fn get_at(&self, ticker: &str, now: Instant) -> Tradability {
+        self.states
+            .read()
+            .unwrap()
+            .get(ticker)
+            .map_or(Tradability::Normal, |state| current(*state, now))
+    }
+
+    /// 매매정지 목록으로 갈아끼운다. 목록에서 빠진 종목은 정지 해제로 본다.
+    /// 상태가 바뀐 종목의 이벤트를 돌려준다.
+    pub fn set_halted(&self, tickers: &[String]) -> Vec<TradabilityEvent> {
+        let mut states = self.states.write().unwrap();
+        let mut events = Vec::new();
+        let released: Vec<String> = states
+            .iter()
+            .filter(|(ticker, (state, _))| {
+                *state == Tradability::Halted && !tickers.contains(ticker)
+            })
+            .map(|(ticker, _)| ticker.clone())
+            .collect();
+        for ticker in released {
+            states.remove(&ticker);
+            events.push(TradabilityEvent {
+                ticker,
+                tradability: Tradability::Normal,
+                at: Utc::now(),
+            });
+        }
+        for ticker in tickers {
+            let previous = states.insert(ticker.clone(), (Tradability::Halted, Instant::now()));
+            if previous.map(|(state, _)| state) != Some(Tradability::Halted) {
+                events.push(TradabilityEvent {
+                    ticker: ticker.clone(),
+                    tradability: Tradability::Halted,
+                    at: Utc::now(),
+                });
+            }
+        }
+        events
+    }
+}
+
+// 오래된 VI 는 풀린 것으로 본다.
+fn current((tradability, since): (Tradability, Instant), now: Instant) -> Tradability {
+    match tradability {
+        Tradability::Vi { .. } if now.saturating_duration_since(since) >= VI_TIMEOUT => {
+            Tradability::Normal
+        }
+        tradability => tradability,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn vi(ticker: &str, gubun: &str) -> Value {
+        json!({
+            "header": {"tr_cd": "VI_", "tr_key": "000000"},
+            "body": {
+                "vi_gubun": gubun, "svi_recprice": "70000", "dvi_recprice": "0",
+                "vi_trgprice": "77000", "shcode": ticker, "ref_shcode": ticker,
+                "time": "093000", "exchname": ""
+            }
+        })
+    }
+
+    #[test]
+    fn test_parse_vi() {
+        let event = parse_vi(&vi("005930", "1")).unwrap();
+        assert_eq!(event.ticker, "005930");
+        assert_eq!(
+            event.tradability,
+            Tradability::Vi {
+                kind: ViKind::Static,
+                trigger_price: 77000
+            }
+        );
+        let released = parse_vi(&vi("005930", "0")).unwrap();
+        assert_eq!(released.tradability, Tradability::Normal);
+
+        let tracker = TradabilityTracker::new();
+        assert_eq!(tracker.apply(&event), Tradability::Normal);
+        assert_eq!(tracker.get("005930"), event.tradability);
+        assert_eq!(tracker.apply(&released), event.tradability);
+        assert_eq!(tracker.get("005930"), Tradability::Normal);
+    }
+
+    #[test]
+    fn test_vi_timeout() {
+        let tracker = TradabilityTracker::new();
+        let event = parse_vi(&vi("005930", "2")).unwrap();
+        let start = Instant::now();
+        tracker.apply_at(&event, start);
+        assert_eq!(
+            tracker.get_at("005930", start + Duration::from_secs(150)),
+            event.tradability
+        );
+        // 해제 이벤트를 놓쳐도 풀린다.
+        assert_eq!(
+            tracker.get_at("005930", start + VI_TIMEOUT),
+            Tradability::Normal
+        );
+        assert_eq!(
+            tracker.apply_at(&event, start + VI_TIMEOUT),
+            Tradability::Normal
+        );
+
+        // 매매정지는 시간이 지나도 그대로다.
+        tracker.set_halted(&["092190".to_string()]);
+        assert_eq!(
+            tracker.get_at("092190", Instant::now() + VI_TIMEOUT),
+            Tradability::Halted
+        );
+    }
+
+    #[test]
+    fn test_accepts() {
+        use OrderType::*;
+        let vi = Tradability::Vi {
+            kind: ViKind::Dynamic,
+            trigger_price: 0,
+        };
+        assert!(Tradability::Normal.accepts(BestLimit, TimeInForce::Ioc));
+        assert!(vi.accepts(Limit, TimeInForce::Day));
+        assert!(!vi.accepts(Limit, TimeInForce::Ioc));
+        assert!(!vi.accepts(BestLimit, TimeInForce::Day));
+        assert!(!Tradability::Halted.accepts(Limit, TimeInForce::Day));
+    }
+
+    #[test]
+    fn test_set_halted() {
+        let tracker = TradabilityTracker::new();
+        let events = tracker.set_halted(&["005930".to_string(), "092190".to_string()]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(tracker.get("092190"), Tradability::Halted);
+
+        // 다시 받아도 바뀐 게 없으면 이벤트가 없다.
+        let events = tracker.set_halted(&["005930".to_string()]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].ticker, "092190");
+        assert_eq!(events[0].tradability, Tradability::Normal);
+        assert_eq!(tracker.get("005930"), Tradability::Halted);
+    }
+}
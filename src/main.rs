@@ -11,14 +11,20 @@ mod position;
 pub mod schema;
 mod strategies;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use tokio::task::JoinHandle;
 // use tokio_stream::StreamExt;
+use crate::broker::calendar::TradingCalendar;
+use crate::broker::lssec::Environment;
+use crate::broker::paper::PaperBroker;
+use crate::broker::recorder::FrameRecorder;
+use crate::broker::replay::{ReplayBroker, ReplaySpeed};
 use crate::broker::{Broker, OrderAction, OrderType};
 use crate::position::position::PositionManager;
 use crate::storage::postgres::PostgresStorage;
 use dotenvy::dotenv;
 use futures_util::{future, pin_mut, SinkExt, StreamExt, TryFutureExt, TryStreamExt};
+use manager::data::DataManager;
 use manager::trading::TradingManager;
 use strategies::envelope::Envelope;
 use tokio_tungstenite::{
@@ -29,6 +35,16 @@ use tonic::{transport::Server, Request, Response, Status};
 use tracing::{error, info};
 use tracing_subscriber;
 
+const CHART_HISTORY_DAYS: i64 = 365;
+
+fn build_manager(
+    client: impl Broker + Clone + 'static,
+    storage: Arc<PostgresStorage>,
+) -> TradingManager {
+    let po = PositionManager::new(Arc::new(client.clone())).with_storage(storage);
+    TradingManager::new(client, po)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
@@ -37,18 +53,60 @@ async fn main() -> Result<()> {
 
     let key = env::var("LSSEC_KEY")?;
     let secret = env::var("LSSEC_SECRET")?;
-    let client = broker::lssec::LsSecClient::new(key, secret);
-    let pcli = Arc::new(client.clone());
+    // LSSEC_API_URL, LSSEC_WS_URL 이 있으면 그 주소로 (대역 서버 등), 아니면 LSSEC_ENV 로 고른다.
+    let environment = match (env::var("LSSEC_API_URL"), env::var("LSSEC_WS_URL")) {
+        (Ok(api_url), Ok(ws_url)) => Environment::Custom { api_url, ws_url },
+        (Ok(_), Err(_)) | (Err(_), Ok(_)) => {
+            return Err(anyhow!(
+                "LSSEC_API_URL and LSSEC_WS_URL must be set together"
+            ));
+        }
+        (Err(_), Err(_)) => match env::var("LSSEC_ENV") {
+            Ok(name) => Environment::try_from(name.as_str())?,
+            Err(_) => Environment::Production,
+        },
+    };
+    let mut client = broker::lssec::LsSecClient::new(key, secret).with_environment(environment);
+    // FRAME_RECORD_PATH 가 설정되면 웹소켓 프레임을 녹화한다.
+    if let Ok(path) = env::var("FRAME_RECORD_PATH") {
+        client = client.with_recorder(FrameRecorder::open(path)?);
+    }
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let storage = Arc::new(PostgresStorage::new(database_url));
 
-    let po = PositionManager::new(pcli, storage.clone());
-    let mut manager = TradingManager::new(client, po);
-    let envelope = Envelope::new();
+    // PAPER_CASH 가 설정되면 실주문 대신 모의 체결로 동작한다.
+    // REPLAY_PATH 가 함께 설정되면 실시간 시세 대신 녹화 파일을 REPLAY_SPEED 배속으로 재생한다.
+    let manager = match (env::var("PAPER_CASH"), env::var("REPLAY_PATH")) {
+        (Ok(cash), Ok(path)) => {
+            let mut replay =
+                ReplayBroker::open(&path)?.with_instruments(client.get_instruments().await?);
+            if let Ok(speed) = env::var("REPLAY_SPEED") {
+                replay = replay.with_speed(ReplaySpeed::try_from(speed.as_str())?);
+            }
+            info!("replay {}", path);
+            let paper = PaperBroker::new(Arc::new(replay), cash.parse()?);
+            build_manager(paper, Arc::clone(&storage))
+        }
+        (Err(_), Ok(_)) => return Err(anyhow!("REPLAY_PATH needs PAPER_CASH")),
+        (Ok(cash), Err(_)) => {
+            let paper = PaperBroker::new(Arc::new(client.clone()), cash.parse()?);
+            build_manager(paper, Arc::clone(&storage))
+        }
+        (Err(_), Err(_)) => build_manager(client.clone(), Arc::clone(&storage)),
+    };
+    let envelope = Envelope::new().with_storage(Arc::clone(&storage));
     let sample = strategies::sample::SampleStrategy::new();
     manager.add_strategy(Box::new(envelope));
     manager.add_strategy(Box::new(sample));
 
+    // 전략이 읽을 일봉을 charts 테이블에 채운다. 새 대상 종목은 바로, 전체는 장 마감마다 받는다.
+    let data = DataManager::new(Arc::new(client.clone()), Arc::clone(&storage));
+    let targets = manager.target_updates();
+    tokio::spawn(async move {
+        data.run_daily(TradingCalendar::krx(), targets, CHART_HISTORY_DAYS)
+            .await;
+    });
+
     tokio::select! {
         result = manager.run() => {
             if let Err(e) = result {
@@ -60,6 +118,10 @@ async fn main() -> Result<()> {
         }
     }
 
+    if let Err(e) = client.revoke_token().await {
+        error!("failed to revoke access token: {}", e);
+    }
+
     Ok(())
 }
 
@@ -1,6 +1,19 @@
-use anyhow::Result;
+use crate::broker::calendar::TradingCalendar;
+use crate::broker::session::kst_now;
+use crate::broker::{Broker, CandleInterval};
+use crate::storage::models::Chart;
+use crate::storage::postgres::PostgresStorage;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use chrono::{Local, NaiveDate};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+// 장 마감 뒤 시간외 단일가까지 끝나고 일봉을 받는다.
+const DAILY_INGEST_DELAY: Duration = Duration::from_secs(10 * 60);
 
 // 예시 데이터 구조
 pub struct MarketData {
@@ -37,14 +50,93 @@ pub struct HistoricalData {
 //     async fn get_bulk_data(&self, symbols: &[&str]) -> Result<HashMap<String, MarketData>>;
 // }
 
-pub struct DataManager;
+/// 브로커에서 과거 봉을 받아 `charts` 테이블에 쌓는다.
+pub struct DataManager {
+    client: Arc<dyn Broker>,
+    storage: Arc<PostgresStorage>,
+}
 
 impl DataManager {
-    pub fn new() -> Self {
-        Self
+    pub fn new(client: Arc<dyn Broker>, storage: Arc<PostgresStorage>) -> Self {
+        Self { client, storage }
+    }
+
+    pub async fn ingest_candles(
+        &self,
+        ticker: &str,
+        interval: CandleInterval,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<usize> {
+        let candles = self
+            .client
+            .get_candles(ticker, interval, from, to)
+            .await
+            .with_context(|| format!("Failed to download {} candles", ticker))?;
+        let rows: Vec<Chart> = candles.iter().map(Chart::from).collect();
+        self.storage.upsert_charts(&rows)
     }
 
-    // async fn fetch_market_data(&self, symbols: &[&str]) -> Result<H>
+    /// 종목별 최근 `days` 일 일봉을 받는다. 실패한 종목은 건너뛰고 저장한 봉 수를 돌려준다.
+    pub async fn ingest_daily(&self, tickers: &[String], days: i64) -> usize {
+        let to = Local::now().date_naive();
+        let from = to - chrono::Duration::days(days);
+        let mut total = 0;
+        for ticker in tickers {
+            match self
+                .ingest_candles(ticker, CandleInterval::Day, from, to)
+                .await
+            {
+                Ok(count) => total += count,
+                Err(e) => error!("{:#}", e),
+            }
+        }
+        info!(
+            "stored {} daily candles for {} tickers",
+            total,
+            tickers.len()
+        );
+        total
+    }
+
+    /// 대상 종목이 새로 생기면 그 종목을, 장이 끝날 때마다 전체 대상 종목의 최근 `days` 일 일봉을 받는다.
+    pub async fn run_daily(
+        &self,
+        calendar: TradingCalendar,
+        mut targets: watch::Receiver<Vec<String>>,
+        days: i64,
+    ) {
+        let mut ingested: HashSet<String> = HashSet::new();
+        loop {
+            let now = kst_now();
+            let wait = (calendar.next_close(now) - now)
+                .to_std()
+                .unwrap_or_default()
+                + DAILY_INGEST_DELAY;
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {
+                    let all = targets.borrow().clone();
+                    self.ingest_daily(&all, days).await;
+                    ingested = all.into_iter().collect();
+                }
+                changed = targets.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let added: Vec<String> = targets
+                        .borrow_and_update()
+                        .iter()
+                        .filter(|ticker| !ingested.contains(*ticker))
+                        .cloned()
+                        .collect();
+                    if !added.is_empty() {
+                        self.ingest_daily(&added, days).await;
+                        ingested.extend(added);
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
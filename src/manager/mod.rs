@@ -1,3 +1,4 @@
-mod data;
+pub mod data;
+pub mod oms;
 mod risk;
 pub mod trading;
@@ -0,0 +1,724 @@
+//! 주문 관리 (OMS).
+//! 전략 주문마다 클라이언트 주문번호를 붙이고 LS 주문번호(OrdNo) 와 이어서,
+//! 주문/체결 웹소켓 이벤트로 New → Accepted → PartiallyFilled → Filled / Cancelled / Rejected 를 따라간다.
+//! 전송 중 네트워크 오류로 결과를 모르는 주문은 Unconfirmed 로 두고, 이벤트나 당일 주문 목록으로 확인한다.
+//! 끝난 주문은 목록에서 뺀다.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::broker::error::BrokerError;
+use crate::broker::session::kst_now;
+use crate::broker::{
+    self, Fill, OrderAction, OrderFilter, OrderResult, OrderResultType, OrderScope, OrderStatus,
+    TimeInForce,
+};
+use crate::strategies::strategy_base::{OrderDecision, OrderType};
+
+// 주문 응답보다 먼저 온 이벤트를 들고 있는 시간
+const PENDING_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrderState {
+    //전송 전, 주문번호를 받기 전
+    New,
+    //전송 중 네트워크 오류로 접수 여부를 모름
+    Unconfirmed,
+    //접수
+    Accepted,
+    //일부 체결
+    PartiallyFilled,
+    //전량 체결
+    Filled,
+    //취소 (IOC/FOK 잔량 취소 포함)
+    Cancelled,
+    //거부
+    Rejected,
+}
+
+impl OrderState {
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            OrderState::New
+                | OrderState::Unconfirmed
+                | OrderState::Accepted
+                | OrderState::PartiallyFilled
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ManagedOrder {
+    pub client_id: String,
+    pub strategy_id: String,
+    pub symbol: String,
+    pub action: OrderAction,
+    pub order_type: broker::OrderType,
+    pub time_in_force: TimeInForce,
+    pub quantity: i64,
+    pub price: i64,
+    //LS 주문번호. 정정되면 새 번호로 바뀐다.
+    pub order_no: Option<i64>,
+    pub state: OrderState,
+    pub filled_quantity: i64,
+    //체결 금액 합계
+    pub filled_amount: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ManagedOrder {
+    /// 평균 체결가. 체결이 없으면 0
+    pub fn average_price(&self) -> i64 {
+        if self.filled_quantity > 0 {
+            self.filled_amount / self.filled_quantity
+        } else {
+            0
+        }
+    }
+
+    // 이벤트를 반영한다. 끝난 주문에 온 이벤트는 무시한다.
+    fn apply(&mut self, result: &OrderResult) -> bool {
+        if !self.state.is_open() {
+            warn!(
+                "{} is already {:?}, ignore {:?}",
+                self.client_id, self.state, result.result
+            );
+            return false;
+        }
+        self.state = match result.result {
+            OrderResultType::Wait => match self.state {
+                OrderState::New | OrderState::Unconfirmed => OrderState::Accepted,
+                state => state,
+            },
+            OrderResultType::PartiallyFilled | OrderResultType::Success => {
+                let Some(fill) = &result.fill else {
+                    return false;
+                };
+                self.filled_quantity += fill.quantity;
+                self.filled_amount += fill.quantity * fill.price;
+                if fill.remaining > 0 && self.filled_quantity < self.quantity {
+                    OrderState::PartiallyFilled
+                } else {
+                    OrderState::Filled
+                }
+            }
+            // 정정은 주문번호만 바뀌고 상태는 그대로다.
+            OrderResultType::Edit => {
+                self.order_no = result.id.parse().ok().or(self.order_no);
+                self.state
+            }
+            OrderResultType::Cancel => OrderState::Cancelled,
+            OrderResultType::Denied => OrderState::Rejected,
+        };
+        self.updated_at = Utc::now();
+        true
+    }
+}
+
+/// 이벤트를 반영한 뒤의 주문과, 체결이었다면 그 체결
+#[derive(Clone, Debug)]
+pub struct OrderUpdate {
+    pub order: ManagedOrder,
+    pub fill: Option<Fill>,
+}
+
+#[derive(Default)]
+struct Book {
+    //열린 주문만 둔다
+    orders: HashMap<String, ManagedOrder>,
+    //LS 주문번호 -> 클라이언트 주문번호
+    order_nos: HashMap<i64, String>,
+    //끝난 주문의 LS 주문번호. 늦게 온 이벤트를 버리고, 확인할 때 다른 주문과 헷갈리지 않게 한다.
+    closed: HashSet<i64>,
+    //주문 응답보다 먼저 온 이벤트. PENDING_TTL 이 지나면 버린다.
+    pending: HashMap<i64, (Instant, Vec<OrderResult>)>,
+}
+
+impl Book {
+    // 정정/취소 확인은 원주문번호로 찾는다.
+    fn client_id(&self, result: &OrderResult) -> Option<String> {
+        let original = result.original_id.as_deref().and_then(|id| id.parse().ok());
+        let id = result.id.parse().ok();
+        original
+            .into_iter()
+            .chain(id)
+            .find_map(|no| self.order_nos.get(&no).cloned())
+    }
+
+    fn apply(&mut self, result: &OrderResult) -> Vec<OrderUpdate> {
+        self.apply_at(result, Instant::now())
+    }
+
+    fn apply_at(&mut self, result: &OrderResult, now: Instant) -> Vec<OrderUpdate> {
+        self.pending
+            .retain(|_, (at, _)| now.duration_since(*at) < PENDING_TTL);
+        let no: Option<i64> = result.id.parse().ok();
+        let mut updates = Vec::new();
+        // 끝난 주문에 늦게 온 이벤트는 버린다.
+        if no.is_some_and(|no| self.closed.contains(&no)) {
+            return updates;
+        }
+        let client_id = match self.client_id(result) {
+            Some(client_id) => client_id,
+            None => match self.find_unconfirmed(result) {
+                // 확인 안 된 주문의 이벤트면 이어 주고 모아 둔 이벤트부터 반영한다.
+                Some(client_id) => {
+                    updates = self.link(&client_id, no.unwrap());
+                    client_id
+                }
+                None => {
+                    // 주문번호를 받기 전에 온 이벤트는 모아 뒀다가 이어질 때 반영한다.
+                    if let Some(no) = no {
+                        self.pending
+                            .entry(no)
+                            .or_insert_with(|| (now, Vec::new()))
+                            .1
+                            .push(result.clone());
+                    }
+                    return updates;
+                }
+            },
+        };
+        updates.extend(self.update(&client_id, result));
+        updates
+    }
+
+    fn update(&mut self, client_id: &str, result: &OrderResult) -> Option<OrderUpdate> {
+        let order = self.orders.get_mut(client_id)?;
+        if !order.apply(result) {
+            return None;
+        }
+        let order = order.clone();
+        if let Some(no) = order.order_no {
+            self.order_nos.insert(no, client_id.to_string());
+        }
+        if !order.state.is_open() {
+            self.finish(client_id);
+        }
+        Some(OrderUpdate {
+            order,
+            fill: result.fill.clone(),
+        })
+    }
+
+    // 모르는 신규 주문번호의 이벤트가 같은 종목(체결이면 같은 방향)의 확인 안 된 주문 하나에만 맞고,
+    // 응답을 기다리는 주문이 없을 때 그 주문의 이벤트로 본다.
+    fn find_unconfirmed(&self, result: &OrderResult) -> Option<String> {
+        if result.original_id.is_some() || result.id.parse::<i64>().is_err() {
+            return None;
+        }
+        let ticker = result.ticker.as_deref()?;
+        let same_ticker = || self.orders.values().filter(move |o| o.symbol == ticker);
+        if same_ticker().any(|o| o.state == OrderState::New) {
+            return None;
+        }
+        let mut candidates = same_ticker().filter(|o| {
+            o.state == OrderState::Unconfirmed
+                && result.fill.as_ref().is_none_or(|f| f.action == o.action)
+        });
+        let order = candidates.next()?;
+        candidates.next().is_none().then(|| order.client_id.clone())
+    }
+
+    // 주문번호를 이어 접수된 것으로 보고, 먼저 와 있던 이벤트를 반영한다.
+    fn link(&mut self, client_id: &str, no: i64) -> Vec<OrderUpdate> {
+        let Some(order) = self.orders.get_mut(client_id) else {
+            return Vec::new();
+        };
+        order.order_no = Some(no);
+        order.state = OrderState::Accepted;
+        order.updated_at = Utc::now();
+        self.order_nos.insert(no, client_id.to_string());
+
+        let pending = self
+            .pending
+            .remove(&no)
+            .map(|(_, results)| results)
+            .unwrap_or_default();
+        pending
+            .iter()
+            .filter_map(|result| self.update(client_id, result))
+            .collect()
+    }
+
+    // 주문 목록과 비교해서 이벤트로 못 받은 체결과 취소/거부를 채운다.
+    fn catch_up(&mut self, client_id: &str, placed: &broker::Order) -> Vec<OrderUpdate> {
+        let Some(order) = self.orders.get(client_id) else {
+            return Vec::new();
+        };
+        let mut results = Vec::new();
+        let quantity = placed.filled_quantity - order.filled_quantity;
+        if quantity > 0 {
+            let amount = placed.filled_price * placed.filled_quantity - order.filled_amount;
+            let remaining = placed.remaining_quantity;
+            results.push(OrderResult {
+                id: placed.id.to_string(),
+                result: if remaining > 0 {
+                    OrderResultType::PartiallyFilled
+                } else {
+                    OrderResultType::Success
+                },
+                original_id: None,
+                ticker: Some(placed.symbol.clone()),
+                fill: Some(Fill {
+                    action: placed.action,
+                    quantity,
+                    price: amount / quantity,
+                    time: kst_now().time(),
+                    remaining,
+                }),
+            });
+        }
+        let closed = match placed.status {
+            OrderStatus::Cancelled => Some(OrderResultType::Cancel),
+            OrderStatus::Rejected => Some(OrderResultType::Denied),
+            _ => None,
+        };
+        if let Some(result) = closed {
+            results.push(OrderResult {
+                id: placed.id.to_string(),
+                result,
+                original_id: None,
+                ticker: Some(placed.symbol.clone()),
+                fill: None,
+            });
+        }
+        results
+            .iter()
+            .filter_map(|result| self.update(client_id, result))
+            .collect()
+    }
+
+    // 끝난 주문은 목록에서 빼고 주문번호만 남긴다.
+    fn finish(&mut self, client_id: &str) {
+        self.orders.remove(client_id);
+        let closed = &mut self.closed;
+        self.order_nos.retain(|no, id| {
+            if id == client_id {
+                closed.insert(*no);
+            }
+            id != client_id
+        });
+    }
+}
+
+pub struct OrderManager {
+    client: Arc<dyn broker::Broker>,
+    book: Mutex<Book>,
+    next_id: AtomicU64,
+}
+
+impl OrderManager {
+    pub fn new(client: Arc<dyn broker::Broker>) -> Self {
+        Self {
+            client,
+            book: Mutex::new(Book::default()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 전략 주문을 `price` 로 보내고 클라이언트 주문번호를 돌려준다. 거부되면 목록에서 뺀다.
+    /// 네트워크 오류면 Unconfirmed 로 남기니 `reconcile` 로 확인한다.
+    /// 주문 응답 전에 도착한 이벤트가 있으면 함께 반영해서 돌려준다.
+    pub async fn submit(
+        &self,
+        strategy_id: &str,
+        decision: &OrderDecision,
+        price: i64,
+    ) -> Result<(String, Vec<OrderUpdate>)> {
+        let action = match decision.order_type {
+            OrderType::Buy => OrderAction::Buy,
+            OrderType::Sell => OrderAction::Sell,
+            OrderType::Hold => return Err(anyhow!("hold is not an order")),
+        };
+        let symbol = decision.symbol.as_str();
+        let quantity = decision.quantity as i64;
+        let (order_type, time_in_force) = (decision.price_type, decision.time_in_force);
+        let client_id = format!(
+            "{}-{}",
+            strategy_id,
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let order = ManagedOrder {
+            client_id: client_id.clone(),
+            strategy_id: strategy_id.to_string(),
+            symbol: symbol.to_string(),
+            action,
+            order_type,
+            time_in_force,
+            quantity,
+            price,
+            order_no: None,
+            state: OrderState::New,
+            filled_quantity: 0,
+            filled_amount: 0,
+            updated_at: Utc::now(),
+        };
+        self.book
+            .lock()
+            .await
+            .orders
+            .insert(client_id.clone(), order);
+
+        let result = self
+            .client
+            .order(symbol, quantity, price, action, order_type, time_in_force)
+            .await;
+
+        let mut book = self.book.lock().await;
+        let order = book.orders.get_mut(&client_id).unwrap();
+        order.updated_at = Utc::now();
+        let placed = match result {
+            Ok(placed) => placed,
+            Err(e) => {
+                if matches!(e.downcast_ref(), Some(BrokerError::Transport(_))) {
+                    order.state = OrderState::Unconfirmed;
+                } else {
+                    order.state = OrderState::Rejected;
+                    book.finish(&client_id);
+                }
+                return Err(e);
+            }
+        };
+        // 주문번호를 받았으면 접수된 것으로 본다.
+        let updates = book.link(&client_id, placed.id);
+        Ok((client_id, updates))
+    }
+
+    /// 접수 여부를 모르는 주문을 당일 주문 목록에서 같은 종목, 방향, 수량, 가격의 모르는 주문번호로 찾는다.
+    /// 찾으면 이어서 그동안의 이벤트와 빠진 체결을 반영해 돌려주고, 없으면 보내지지 않은 주문으로 보고 빼고 None.
+    /// 이미 확인된 주문이면 빈 목록.
+    pub async fn reconcile(&self, client_id: &str) -> Result<Option<Vec<OrderUpdate>>> {
+        let Some(order) = self.get(client_id).await else {
+            return Ok(Some(Vec::new()));
+        };
+        if order.state != OrderState::Unconfirmed {
+            return Ok(Some(Vec::new()));
+        }
+        let placed = self
+            .client
+            .get_orders(OrderFilter {
+                ticker: Some(order.symbol.clone()),
+                action: Some(order.action),
+                scope: OrderScope::All,
+            })
+            .await?;
+
+        let mut book = self.book.lock().await;
+        // 조회하는 동안 이벤트로 이어졌을 수 있다.
+        if book
+            .orders
+            .get(client_id)
+            .is_none_or(|order| order.state != OrderState::Unconfirmed)
+        {
+            return Ok(Some(Vec::new()));
+        }
+        let found = placed
+            .into_iter()
+            .filter(|p| {
+                p.original_id.is_none()
+                    && p.quantity == order.quantity
+                    && p.price == order.price
+                    && !book.order_nos.contains_key(&p.id)
+                    && !book.closed.contains(&p.id)
+            })
+            .max_by_key(|p| p.id);
+        let Some(placed) = found else {
+            warn!("order {} was not placed", client_id);
+            book.finish(client_id);
+            return Ok(None);
+        };
+        let mut updates = book.link(client_id, placed.id);
+        updates.extend(book.catch_up(client_id, &placed));
+        Ok(Some(updates))
+    }
+
+    /// 주문/체결 이벤트를 반영한다. 확인 안 된 주문이 이어지면 먼저 와 있던 이벤트도 함께 돌려준다.
+    pub async fn apply(&self, result: &OrderResult) -> Vec<OrderUpdate> {
+        self.book.lock().await.apply(result)
+    }
+
+    pub async fn get(&self, client_id: &str) -> Option<ManagedOrder> {
+        self.book.lock().await.orders.get(client_id).cloned()
+    }
+
+    pub async fn open_orders(&self) -> Vec<ManagedOrder> {
+        let mut orders: Vec<ManagedOrder> = self
+            .book
+            .lock()
+            .await
+            .orders
+            .values()
+            .filter(|order| order.state.is_open())
+            .cloned()
+            .collect();
+        orders.sort_by_key(|order| order.updated_at);
+        orders
+    }
+
+    pub async fn open_orders_by_strategy(&self, strategy_id: &str) -> Vec<ManagedOrder> {
+        self.open_orders()
+            .await
+            .into_iter()
+            .filter(|order| order.strategy_id == strategy_id)
+            .collect()
+    }
+
+    pub async fn open_orders_by_symbol(&self, symbol: &str) -> Vec<ManagedOrder> {
+        self.open_orders()
+            .await
+            .into_iter()
+            .filter(|order| order.symbol == symbol)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::broker::lssec::LsSecClient;
+    use crate::broker::mock::MockLsServer;
+    use chrono::NaiveTime;
+
+    fn oms(server: &MockLsServer) -> OrderManager {
+        let client = LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment());
+        OrderManager::new(Arc::new(client))
+    }
+
+    fn event(id: &str, result: OrderResultType) -> OrderResult {
+        OrderResult {
+            id: id.to_string(),
+            result,
+            original_id: None,
+            ticker: Some("005930".to_string()),
+            fill: None,
+        }
+    }
+
+    fn fill(id: &str, quantity: i64, remaining: i64) -> OrderResult {
+        OrderResult {
+            fill: Some(Fill {
+                action: OrderAction::Buy,
+                quantity,
+                price: 70000,
+                time: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+                remaining,
+            }),
+            ..event(
+                id,
+                if remaining > 0 {
+                    OrderResultType::PartiallyFilled
+                } else {
+                    OrderResultType::Success
+                },
+            )
+        }
+    }
+
+    fn decision(symbol: &str, quantity: u32) -> OrderDecision {
+        OrderDecision {
+            order_type: OrderType::Buy,
+            symbol: symbol.to_string(),
+            quantity,
+            price: 70000.0,
+            price_type: broker::OrderType::Limit,
+            time_in_force: TimeInForce::Day,
+            reason: "test".to_string(),
+        }
+    }
+
+    async fn buy(oms: &OrderManager, strategy_id: &str, symbol: &str) -> String {
+        oms.submit(strategy_id, &decision(symbol, 3), 70000)
+            .await
+            .unwrap()
+            .0
+    }
+
+    #[tokio::test]
+    async fn test_order_lifecycle() {
+        let server = MockLsServer::start().await.unwrap();
+        let oms = oms(&server);
+
+        let id = buy(&oms, "envelope", "005930").await;
+        let order = oms.get(&id).await.unwrap();
+        assert_eq!(order.state, OrderState::Accepted);
+        let no = order.order_no.unwrap().to_string();
+
+        assert_eq!(oms.apply(&event(&no, OrderResultType::Wait)).await.len(), 1);
+        let update = oms.apply(&fill(&no, 1, 2)).await.pop().unwrap();
+        assert_eq!(update.order.state, OrderState::PartiallyFilled);
+        assert_eq!(update.fill.unwrap().quantity, 1);
+        let update = oms.apply(&fill(&no, 2, 0)).await.pop().unwrap();
+        assert_eq!(update.order.state, OrderState::Filled);
+        assert_eq!(update.order.average_price(), 70000);
+        assert!(oms.open_orders().await.is_empty());
+        // 끝난 주문은 목록에서 빠진다.
+        assert!(oms.get(&id).await.is_none());
+
+        // 끝난 주문에 온 이벤트는 무시한다.
+        assert!(oms
+            .apply(&event(&no, OrderResultType::Cancel))
+            .await
+            .is_empty());
+        assert!(oms.book.lock().await.pending.is_empty());
+        // 모르는 주문
+        assert!(oms
+            .apply(&event("999", OrderResultType::Denied))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_amend_and_cancel() {
+        let server = MockLsServer::start().await.unwrap();
+        let oms = oms(&server);
+        let id = buy(&oms, "envelope", "005930").await;
+        let no = oms.get(&id).await.unwrap().order_no.unwrap();
+
+        // 정정되면 새 주문번호로 이어진다.
+        let mut amended = event(&(no + 100).to_string(), OrderResultType::Edit);
+        amended.original_id = Some(no.to_string());
+        let update = oms.apply(&amended).await.pop().unwrap();
+        assert_eq!(update.order.order_no, Some(no + 100));
+        assert_eq!(update.order.state, OrderState::Accepted);
+
+        let mut cancelled = event(&(no + 101).to_string(), OrderResultType::Cancel);
+        cancelled.original_id = Some((no + 100).to_string());
+        let update = oms.apply(&cancelled).await.pop().unwrap();
+        assert_eq!(update.order.client_id, id);
+        assert_eq!(update.order.state, OrderState::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_open_orders() {
+        let server = MockLsServer::start().await.unwrap();
+        let oms = oms(&server);
+        buy(&oms, "envelope", "005930").await;
+        buy(&oms, "envelope", "005935").await;
+        buy(&oms, "sample", "005930").await;
+
+        assert_eq!(oms.open_orders().await.len(), 3);
+        assert_eq!(oms.open_orders_by_strategy("envelope").await.len(), 2);
+        let orders = oms.open_orders_by_symbol("005930").await;
+        assert_eq!(orders.len(), 2);
+        assert!(orders.iter().any(|o| o.strategy_id == "sample"));
+
+        // 거부된 주문은 목록에 남지 않는다.
+        server
+            .reject_orders("02714", "주문수량이 매매단위의 배수가 아닙니다")
+            .await;
+        assert!(oms
+            .submit("sample", &decision("005930", 1), 70000)
+            .await
+            .is_err());
+        assert!(oms.get("sample-4").await.is_none());
+        assert_eq!(oms.open_orders().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_event_before_order_response() {
+        let server = MockLsServer::start().await.unwrap();
+        let oms = oms(&server);
+
+        // 모의 서버는 1번부터 주문번호를 준다.
+        assert!(oms.apply(&fill("1", 3, 0)).await.is_empty());
+        let (id, updates) = oms
+            .submit("envelope", &decision("005930", 3), 70000)
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].order.client_id, id);
+        assert_eq!(updates[0].order.state, OrderState::Filled);
+    }
+
+    #[test]
+    fn test_pending_expiry() {
+        let mut book = Book::default();
+        let now = Instant::now();
+        book.apply_at(&fill("1", 3, 0), now);
+        book.apply_at(&fill("2", 3, 0), now + Duration::from_secs(1));
+        assert_eq!(book.pending.len(), 2);
+
+        // 오래된 이벤트는 다음 이벤트가 올 때 버린다.
+        book.apply_at(&fill("3", 3, 0), now + PENDING_TTL);
+        let mut nos: Vec<i64> = book.pending.keys().copied().collect();
+        nos.sort();
+        assert_eq!(nos, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_order() {
+        let server = MockLsServer::start().await.unwrap();
+        let oms = oms(&server);
+
+        // 응답만 잃은 주문은 주문 목록에서 찾아 잇는다.
+        server.lose_orders(true).await;
+        let e = oms
+            .submit("envelope", &decision("005930", 3), 70000)
+            .await
+            .unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(BrokerError::Transport(_))));
+        let order = oms.open_orders().await.pop().unwrap();
+        assert_eq!(order.state, OrderState::Unconfirmed);
+        server.push_execution(1, "005930", 2, 70000, 1).await;
+        let updates = oms.reconcile(&order.client_id).await.unwrap().unwrap();
+        let order = oms.get(&order.client_id).await.unwrap();
+        assert_eq!(order.order_no, Some(1));
+        assert_eq!(order.state, OrderState::PartiallyFilled);
+        assert_eq!(updates.last().unwrap().fill.as_ref().unwrap().quantity, 2);
+
+        // 보내지지 않은 주문은 빠진다.
+        server.lose_orders(false).await;
+        assert!(oms
+            .submit("sample", &decision("005935", 1), 57000)
+            .await
+            .is_err());
+        let lost = oms.open_orders_by_strategy("sample").await.pop().unwrap();
+        assert!(oms.reconcile(&lost.client_id).await.unwrap().is_none());
+        assert!(oms.get(&lost.client_id).await.is_none());
+    }
+
+    #[test]
+    fn test_unconfirmed_from_event() {
+        let mut book = Book::default();
+        let order = |client_id: &str, state| ManagedOrder {
+            client_id: client_id.to_string(),
+            strategy_id: "envelope".to_string(),
+            symbol: "005930".to_string(),
+            action: OrderAction::Buy,
+            order_type: broker::OrderType::Limit,
+            time_in_force: TimeInForce::Day,
+            quantity: 3,
+            price: 70000,
+            order_no: None,
+            state,
+            filled_quantity: 0,
+            filled_amount: 0,
+            updated_at: Utc::now(),
+        };
+        book.orders
+            .insert("a".to_string(), order("a", OrderState::Unconfirmed));
+        book.orders
+            .insert("b".to_string(), order("b", OrderState::New));
+
+        // 응답을 기다리는 주문이 있으면 누구 것인지 모르니 모아 둔다.
+        assert!(book.apply(&event("7", OrderResultType::Wait)).is_empty());
+        book.orders.remove("b");
+
+        // 접수 이벤트와 함께 모아 둔 이벤트도 반영한다.
+        let updates = book.apply(&fill("7", 3, 0));
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].order.state, OrderState::Filled);
+        assert!(book.orders.is_empty());
+        assert!(book.closed.contains(&7));
+    }
+}
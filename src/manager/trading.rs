@@ -3,21 +3,135 @@ use crate::strategies::strategy_base::{OrderDecision, OrderType, Strategy};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures::future::join_all;
-use std::collections::HashSet;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::signal;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::channel;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Notify};
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 // use futures::{StreamExt};
-use crate::broker::Tick;
+use crate::broker::error::BrokerError;
+use crate::broker::price_rules::{round_to_tick, Rounding};
+use crate::broker::session::{self, SessionTracker};
+use crate::broker::subscription::{Feed, SubscriptionManager};
+use crate::broker::tradability::Tradability;
+use crate::broker::{ConnectionState, OrderBook, Tick};
+use crate::manager::oms::{OrderManager, OrderState, OrderUpdate};
 use crate::position::position::PositionManager;
+use crate::position::Position;
 use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
 use tokio_stream::StreamExt;
 use tokio_util::io::StreamReader;
 use tonic::codegen::Body;
 
+const ORDER_RETRY_DELAY: Duration = Duration::from_secs(1);
+// 전략 대상 종목을 다시 받아 실시간 등록을 맞추는 간격
+const TARGET_REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+type SharedStrategy = Arc<dyn Strategy>;
+
+// 실행 중에도 전략을 더하고 뺄 수 있도록 매번 목록을 복사해서 쓴다.
+fn snapshot(strategies: &RwLock<Vec<SharedStrategy>>) -> Vec<SharedStrategy> {
+    strategies.read().unwrap().clone()
+}
+
+// 전략 대상 종목 조회는 파이썬 호출처럼 오래 걸릴 수 있어 blocking 스레드에서 돌린다.
+async fn fetch_targets(
+    strategies: Vec<SharedStrategy>,
+) -> Vec<(SharedStrategy, Result<Vec<String>>)> {
+    let handles: Vec<_> = strategies
+        .into_iter()
+        .map(|strategy| {
+            tokio::task::spawn_blocking(move || {
+                let targets = strategy.get_targets();
+                (strategy, targets)
+            })
+        })
+        .collect();
+    let mut results = Vec::new();
+    for handle in handles {
+        match handle.await {
+            Ok(result) => results.push(result),
+            Err(e) => error!("target lookup task failed: {}", e),
+        }
+    }
+    results
+}
+
+// 체결을 전략별 잔고로 기록한다. 매도는 음수 수량.
+fn record_fill(position_manager: &PositionManager, update: &OrderUpdate) -> Result<()> {
+    let Some(fill) = &update.fill else {
+        return Ok(());
+    };
+    let amount = match fill.action {
+        broker::OrderAction::Buy => fill.quantity,
+        broker::OrderAction::Sell => -fill.quantity,
+    };
+    position_manager.add_position(Position {
+        id: uuid::Uuid::new_v4(),
+        ticker: update.order.symbol.clone(),
+        price: fill.price as f64,
+        amount: amount as f64,
+        strategy_id: update.order.strategy_id.clone(),
+        created_at: chrono::Local::now().naive_local(),
+    })
+}
+
+// 전략별 체결/호가 처리 작업
+struct StrategyWorker {
+    targets: Arc<RwLock<HashSet<String>>>,
+    book_targets: Arc<RwLock<HashSet<String>>>,
+    cancel: CancellationToken,
+}
+
+fn is_retryable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<BrokerError>()
+        .is_some_and(|e| e.is_retryable())
+}
+
+fn is_transport(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref(), Some(BrokerError::Transport(_)))
+}
+
+// 전략이 낸 가격을 호가 단위와 상/하한가 안으로 맞춘다.
+// 매수는 내림, 매도는 올림해서 전략이 정한 가격보다 불리해지지 않게 한다.
+async fn limit_price(
+    client: &dyn broker::Broker,
+    decision: &OrderDecision,
+    action: broker::OrderAction,
+) -> Result<i64> {
+    let instrument = client.get_instrument(&decision.symbol).await?;
+    let rounding = match action {
+        broker::OrderAction::Buy => Rounding::Down,
+        broker::OrderAction::Sell => Rounding::Up,
+    };
+    let price = round_to_tick(&instrument, decision.price as i64, rounding);
+    Ok(match instrument.price_limits() {
+        Some(limits) => limits.clamp(price),
+        None => price,
+    })
+}
+
+// VI 단일가 중에는 전략 가격의 당일 지정가로 바꿔 급변한 가격에 체결되지 않게 한다.
+// 매매정지 종목이나 가격이 없는 주문은 None.
+fn adjust_for_tradability(
+    decision: &OrderDecision,
+    tradability: Tradability,
+) -> Option<OrderDecision> {
+    match tradability {
+        Tradability::Normal => Some(decision.clone()),
+        Tradability::Vi { .. } if decision.price > 0.0 => Some(OrderDecision {
+            price_type: broker::OrderType::Limit,
+            time_in_force: broker::TimeInForce::Day,
+            ..decision.clone()
+        }),
+        Tradability::Vi { .. } | Tradability::Halted => None,
+    }
+}
+
 #[async_trait]
 pub trait OrderExecutor: Send + Sync {
     async fn execute_buy(&self, symbol: &str, quantity: i32) -> Result<()>;
@@ -25,42 +139,186 @@ pub trait OrderExecutor: Send + Sync {
 }
 
 pub struct TradingManager {
-    strategies: Vec<Arc<Mutex<Box<dyn Strategy>>>>,
+    strategies: Arc<RwLock<Vec<SharedStrategy>>>,
+    // 전략이 더해지거나 빠지면 대상 종목을 다시 맞춘다.
+    strategy_changed: Notify,
     client: Arc<dyn broker::Broker>,
+    subscriptions: SubscriptionManager,
+    book_subscriptions: SubscriptionManager,
+    oms: Arc<OrderManager>,
     position_manager: PositionManager,
+    sessions: SessionTracker,
+    // 모든 전략 대상 종목의 합집합. 대상 종목을 다시 맞출 때마다 갱신한다.
+    targets: watch::Sender<Vec<String>>,
 }
 
 impl TradingManager {
     pub fn new(client: impl broker::Broker + 'static, position_manager: PositionManager) -> Self {
+        let client: Arc<dyn broker::Broker> = Arc::new(client);
         Self {
-            strategies: Vec::new(),
-            client: Arc::new(client),
+            strategies: Arc::new(RwLock::new(Vec::new())),
+            strategy_changed: Notify::new(),
+            subscriptions: SubscriptionManager::new(Arc::clone(&client)),
+            book_subscriptions: SubscriptionManager::new(Arc::clone(&client))
+                .with_feed(Feed::OrderBook),
+            oms: Arc::new(OrderManager::new(Arc::clone(&client))),
+            client,
             position_manager,
+            sessions: SessionTracker::new(),
+            targets: watch::channel(Vec::new()).0,
+        }
+    }
+
+    pub fn add_strategy(&self, strategy: Box<dyn Strategy>) {
+        self.strategies.write().unwrap().push(Arc::from(strategy));
+        self.strategy_changed.notify_one();
+    }
+
+    /// `id` 전략을 빼고 그 전략만 쓰던 종목은 실시간 등록을 해제한다.
+    pub async fn remove_strategy(&self, id: &str) -> bool {
+        let mut removed = false;
+        for strategy in snapshot(&self.strategies) {
+            if strategy.get_id() == id {
+                self.strategies
+                    .write()
+                    .unwrap()
+                    .retain(|s| !Arc::ptr_eq(s, &strategy));
+                removed = true;
+            }
         }
+        if removed {
+            self.strategy_changed.notify_one();
+        }
+        removed
     }
 
-    pub fn add_strategy(&mut self, strategy: Box<dyn Strategy>) {
-        self.strategies.push(Arc::new(Mutex::new(strategy)));
+    /// 전략 주문 상태 (전략별, 종목별 미체결 조회)
+    pub fn orders(&self) -> Arc<OrderManager> {
+        Arc::clone(&self.oms)
     }
 
+    /// 실행 중 대상 종목이 바뀔 때마다 합집합을 받는다.
+    pub fn target_updates(&self) -> watch::Receiver<Vec<String>> {
+        self.targets.subscribe()
+    }
+
+    /// 모든 전략 대상 종목의 합집합. 대상 종목을 못 받은 전략은 빼고 모은다.
     pub async fn get_all_targets(&self) -> Result<Vec<String>> {
-        let mut targets = Vec::new();
-        for strategy in &self.strategies {
-            let strategy = strategy.lock().await;
-            targets.extend(strategy.get_targets());
+        let mut targets = HashSet::new();
+        for (strategy, result) in fetch_targets(snapshot(&self.strategies)).await {
+            match result {
+                Ok(strategy_targets) => targets.extend(strategy_targets),
+                Err(e) => error!(
+                    "strategy {} failed to get targets: {:#}",
+                    strategy.get_id(),
+                    e
+                ),
+            }
         }
+        let mut targets: Vec<String> = targets.into_iter().collect();
+        targets.sort();
         Ok(targets)
     }
 
     pub async fn run(&self) -> Result<()> {
+        // 전략별 잔고는 시작할 때 한 번 읽고 이후엔 체결로 갱신한다.
+        let positions = self.position_manager.clone();
+        tokio::task::spawn_blocking(move || positions.load())
+            .await?
+            .context("failed to load positions")?;
         let (mut tx, mut rx) = tokio::sync::broadcast::channel::<Tick>(100);
         let cancel = CancellationToken::new();
         let socket_cancel = cancel.clone();
+        let mut connection_events = self.client.connection_events();
+        tokio::spawn(async move {
+            // 스트림마다 공백이 시작된 시각
+            let mut disconnected_at = HashMap::new();
+            loop {
+                match connection_events.recv().await {
+                    Ok(event) => match event.state {
+                        ConnectionState::Disconnected => {
+                            error!("{:?} stream disconnected, data gap started", event.stream);
+                            disconnected_at.entry(event.stream).or_insert(event.at);
+                        }
+                        ConnectionState::Reconnected => {
+                            if let Some(at) = disconnected_at.remove(&event.stream) {
+                                info!(
+                                    "{:?} stream reconnected after {}s gap",
+                                    event.stream,
+                                    (event.at - at).num_seconds()
+                                );
+                            }
+                        }
+                        ConnectionState::Connected => {}
+                    },
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        let mut session_events = self.client.session_events();
+        let sessions = self.sessions.clone();
+        let session_strategies = Arc::clone(&self.strategies);
+        tokio::spawn(async move {
+            loop {
+                let event = match session_events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        error!("dropped {} session events", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let previous = sessions.apply(&event);
+                info!(
+                    "{} session {:?} -> {:?}",
+                    event.market, previous, event.phase
+                );
+                let open = session::is_open_transition(previous, event.phase);
+                let close = session::is_close_transition(previous, event.phase);
+                if !open && !close {
+                    continue;
+                }
+                for strategy in snapshot(&session_strategies) {
+                    let result = if open {
+                        strategy.on_market_open(event.market).await
+                    } else {
+                        strategy.on_market_close(event.market).await
+                    };
+                    if let Err(e) = result {
+                        error!("strategy {} session hook error: {}", strategy.get_id(), e);
+                    }
+                }
+            }
+        });
         let mut socket = self.client.connect_websocket(socket_cancel).await?;
-        for ticker in &["005930", "005935", "103590"] {
-            self.client.subscribe(ticker).await?;
-        }
-        let (decision_tx, mut decision_rx) = channel(100);
+
+        // 주문/체결 이벤트로 주문 상태를 갱신하고 체결은 잔고에 기록한다.
+        let mut order_results = self
+            .client
+            .connect_websocket_order_transaction(cancel.clone())
+            .await?;
+        let oms = Arc::clone(&self.oms);
+        let fill_positions = self.position_manager.clone();
+        tokio::spawn(async move {
+            while let Some(result) = order_results.recv().await {
+                for update in oms.apply(&result).await {
+                    info!(
+                        "order {} {:?} ({}/{})",
+                        update.order.client_id,
+                        update.order.state,
+                        update.order.filled_quantity,
+                        update.order.quantity
+                    );
+                    if let Err(e) = record_fill(&fill_positions, &update) {
+                        error!("Failed to record fill {}: {}", update.order.client_id, e);
+                    }
+                }
+            }
+        });
+
+        let books = self.client.order_book_stream();
+        let (decision_tx, mut decision_rx) = channel::<(String, OrderDecision)>(100);
         let ttx = tx.clone();
         tokio::spawn(async move {
             while let Some(msg) = socket.recv().await {
@@ -73,83 +331,526 @@ impl TradingManager {
             }
         });
 
-        for strategy in self.strategies.iter().cloned() {
-            let mut tick_rx = rx.resubscribe();
-            let decision_tx = decision_tx.clone();
-            let position_manager = self.position_manager.clone();
-
-            tokio::spawn(async move {
-                while let Ok(tick) = tick_rx.recv().await {
-                    let strategy = strategy.lock().await;
-                    let id = strategy.get_id();
-
-                    let positions = position_manager
-                        .get_positions()
-                        .expect("Failed to get positions")
-                        .into_iter()
-                        .filter(|p| p.strategy_id == id)
-                        .collect::<Vec<_>>();
-
-                    let decision = OrderDecision {
-                        order_type: OrderType::Hold,
-                        symbol: tick.ticker.clone(),
-                        quantity: 0,
-                        price: 0.0,
-                        reason: format!("strategy id: {}, tick: {:?}", id, tick),
-                    };
-                    let _ = decision_tx.send((tick, decision)).await;
+        // 대상 종목을 주기적으로, 또 전략이 바뀔 때마다 다시 맞춘다.
+        // 대상 종목 조회는 따로 돌리고 결과만 받아서, 조회 중에도 주문은 계속 처리한다.
+        let mut workers = HashMap::new();
+        let mut refresh = tokio::time::interval(TARGET_REFRESH_INTERVAL);
+        let (targets_tx, mut targets_rx) = channel::<Vec<(SharedStrategy, Result<Vec<String>>)>>(1);
+        // 조회 중에 다시 요청이 오면 끝난 뒤 한 번 더 조회한다.
+        let mut refreshing = false;
+        let mut requested = false;
+        loop {
+            tokio::select! {
+                _ = refresh.tick() => requested = true,
+                _ = self.strategy_changed.notified() => requested = true,
+                Some(results) = targets_rx.recv() => {
+                    refreshing = false;
+                    self.apply_targets(&mut workers, results, &rx, &books, &decision_tx).await;
                 }
-            });
+                Some((id, decision)) = decision_rx.recv() => {
+                    if let Err(e) = self.execute_decision(&id, &decision, self.client.clone()).await {
+                        error!("strategy {} order failed: {:#}", id, e);
+                    }
+                }
+            }
+            if requested && !refreshing {
+                requested = false;
+                refreshing = true;
+                let strategies = snapshot(&self.strategies);
+                let targets_tx = targets_tx.clone();
+                tokio::spawn(async move {
+                    let _ = targets_tx.send(fetch_targets(strategies).await).await;
+                });
+            }
         }
+    }
 
-        tokio::select! {
-            _ = async {
-                while let Some((tick, decision)) = decision_rx.recv().await {
-                    println!("Tick: {:?}, Decision: {:?}", tick, decision);
+    // 전략별 대상 종목과 호가 종목으로 실시간 등록을 늘리거나 줄이고, 작업을 띄우거나 멈춘다.
+    async fn apply_targets(
+        &self,
+        workers: &mut HashMap<String, StrategyWorker>,
+        results: Vec<(SharedStrategy, Result<Vec<String>>)>,
+        ticks: &tokio::sync::broadcast::Receiver<Tick>,
+        books: &tokio::sync::broadcast::Receiver<OrderBook>,
+        decision_tx: &tokio::sync::mpsc::Sender<(String, OrderDecision)>,
+    ) {
+        let strategies = snapshot(&self.strategies);
+        let active: HashSet<String> = strategies.iter().map(|s| s.get_id()).collect();
+        for (strategy, targets) in results {
+            // 조회하는 동안 빠진 전략은 등록하지 않는다.
+            if !strategies.iter().any(|s| Arc::ptr_eq(s, &strategy)) {
+                continue;
+            }
+            let id = strategy.get_id();
+            // 대상 종목을 못 받으면 이전 대상 종목을 그대로 둔다.
+            let targets: HashSet<String> = match targets {
+                Ok(targets) => targets.into_iter().collect(),
+                Err(e) => {
+                    error!(
+                        "strategy {} failed to get targets, keep previous: {:#}",
+                        id, e
+                    );
+                    continue;
                 }
-            } => {}
+            };
+            let book_targets: HashSet<String> =
+                strategy.get_order_book_targets().into_iter().collect();
+            if let Err(e) = self.subscriptions.set_targets(&id, &targets).await {
+                error!("strategy {} subscription error: {:#}", id, e);
+            }
+            if let Err(e) = self
+                .book_subscriptions
+                .set_targets(&id, &book_targets)
+                .await
+            {
+                error!("strategy {} order book subscription error: {:#}", id, e);
+            }
+            match workers.get(&id) {
+                Some(worker) => {
+                    *worker.targets.write().unwrap() = targets;
+                    *worker.book_targets.write().unwrap() = book_targets;
+                }
+                None => {
+                    let worker = StrategyWorker {
+                        targets: Arc::new(RwLock::new(targets)),
+                        book_targets: Arc::new(RwLock::new(book_targets)),
+                        cancel: CancellationToken::new(),
+                    };
+                    self.spawn_worker(
+                        strategy,
+                        &worker,
+                        ticks.resubscribe(),
+                        books.resubscribe(),
+                        decision_tx.clone(),
+                    );
+                    workers.insert(id, worker);
+                }
+            }
         }
 
-        Ok(())
+        let removed: Vec<String> = workers
+            .keys()
+            .filter(|id| !active.contains(*id))
+            .cloned()
+            .collect();
+        for id in removed {
+            info!("strategy {} removed", id);
+            if let Some(worker) = workers.remove(&id) {
+                worker.cancel.cancel();
+            }
+            if let Err(e) = self.subscriptions.set_targets(&id, &HashSet::new()).await {
+                error!("strategy {} subscription error: {:#}", id, e);
+            }
+            if let Err(e) = self
+                .book_subscriptions
+                .set_targets(&id, &HashSet::new())
+                .await
+            {
+                error!("strategy {} order book subscription error: {:#}", id, e);
+            }
+        }
+
+        let mut targets: Vec<String> = workers
+            .values()
+            .flat_map(|worker| worker.targets.read().unwrap().clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        targets.sort();
+        self.targets.send_if_modified(|current| {
+            let changed = *current != targets;
+            *current = targets;
+            changed
+        });
+    }
+
+    // 대상 종목 체결과 호가 종목 호가만 전략으로 넘긴다. 한 전략의 오류는 그 전략의 해당 체결만 건너뛴다.
+    fn spawn_worker(
+        &self,
+        strategy: SharedStrategy,
+        worker: &StrategyWorker,
+        mut tick_rx: tokio::sync::broadcast::Receiver<Tick>,
+        mut book_rx: tokio::sync::broadcast::Receiver<OrderBook>,
+        decision_tx: tokio::sync::mpsc::Sender<(String, OrderDecision)>,
+    ) {
+        let targets = Arc::clone(&worker.targets);
+        let book_targets = Arc::clone(&worker.book_targets);
+        let cancel = worker.cancel.clone();
+        let position_manager = self.position_manager.clone();
+
+        tokio::spawn(async move {
+            // 호가 스트림이 닫혀도 체결은 계속 받는다.
+            let mut books_open = true;
+            loop {
+                let tick = tokio::select! {
+                    tick = tick_rx.recv() => match tick {
+                        Ok(tick) => tick,
+                        Err(RecvError::Lagged(n)) => {
+                            error!("dropped {} ticks", n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    book = book_rx.recv(), if books_open => {
+                        match book {
+                            Ok(book) if book_targets.read().unwrap().contains(&book.ticker) => {
+                                if let Err(e) = strategy.on_order_book(&book).await {
+                                    error!("strategy {} order book error: {}", strategy.get_id(), e);
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(RecvError::Closed) => books_open = false,
+                            Err(RecvError::Lagged(n)) => error!("dropped {} order book updates", n),
+                        }
+                        continue;
+                    }
+                    _ = cancel.cancelled() => break,
+                };
+                if !targets.read().unwrap().contains(&tick.ticker) {
+                    continue;
+                }
+                let id = strategy.get_id();
+
+                let position = position_manager.get_position(&id, &tick.ticker);
+                let decision = match strategy.evaluate_tick(&tick, position).await {
+                    Ok(decision) => decision,
+                    Err(e) => {
+                        error!("strategy {} evaluate error {}: {}", id, tick.ticker, e);
+                        continue;
+                    }
+                };
+                if matches!(decision.order_type, OrderType::Hold) {
+                    continue;
+                }
+                if decision_tx.send((id, decision)).await.is_err() {
+                    break;
+                }
+            }
+        });
     }
 
     async fn execute_decision(
         &self,
+        strategy_id: &str,
         decision: &OrderDecision,
         client: Arc<dyn broker::Broker>,
     ) -> Result<()> {
-        match decision.order_type {
-            OrderType::Buy => {
-                client
-                    .order(
-                        &decision.symbol,
-                        1,
-                        decision.price as i64,
-                        broker::OrderAction::Buy,
-                        broker::OrderType::Market,
-                    )
-                    .await
-                    .context("Failed to execute buy order")?;
-                log::info!("decision: {}", decision);
-                // client.execute_buy(&decision.symbol, decision.quantity).await
-                //     .context("Failed to execute buy order")?;
-            }
-            OrderType::Sell => {
-                client
-                    .order(
-                        &decision.symbol,
-                        1,
-                        decision.price as i64,
-                        broker::OrderAction::Sell,
-                        broker::OrderType::Market,
-                    )
-                    .await
-                    .context("Failed to execute buy order")?;
-                log::info!("decision: {}", decision);
-            }
-            OrderType::Hold => {}
+        let action = match decision.order_type {
+            OrderType::Buy => broker::OrderAction::Buy,
+            OrderType::Sell => broker::OrderAction::Sell,
+            OrderType::Hold => return Ok(()),
+        };
+        // 같은 전략이 같은 종목, 같은 방향으로 낸 주문이 아직 열려 있으면 새로 내지 않는다.
+        // 접수 여부를 모르는 주문은 먼저 확인한다.
+        for order in self.oms.open_orders_by_symbol(&decision.symbol).await {
+            if order.state == OrderState::Unconfirmed && order.strategy_id == strategy_id {
+                if let Err(e) = self.reconcile(&order.client_id).await {
+                    warn!("failed to confirm order {}: {:#}", order.client_id, e);
+                }
+            }
+        }
+        if let Some(order) = self
+            .oms
+            .open_orders_by_symbol(&decision.symbol)
+            .await
+            .into_iter()
+            .find(|order| order.strategy_id == strategy_id && order.action == action)
+        {
+            info!(
+                "skip {}, order {} is {:?}",
+                decision.symbol, order.client_id, order.state
+            );
+            return Ok(());
+        }
+        let tradability = client.tradability(&decision.symbol);
+        let Some(decision) = &adjust_for_tradability(decision, tradability) else {
+            info!("skip {}, {:?}", decision.symbol, tradability);
+            return Ok(());
+        };
+        if tradability != Tradability::Normal {
+            info!(
+                "reprice {} during {:?}: {}",
+                decision.symbol, tradability, decision
+            );
+        }
+        // 장 운영 상태에 맞지 않는 주문은 보내지 않는다.
+        let market = client.get_instrument(&decision.symbol).await?.market;
+        let phase = self.sessions.phase(market);
+        if !phase.accepts(decision.price_type, decision.time_in_force) {
+            info!(
+                "skip {}, {:?} order not accepted in {:?}",
+                decision.symbol, decision.price_type, phase
+            );
+            return Ok(());
+        }
+
+        let price = if decision.price_type.has_price() {
+            limit_price(client.as_ref(), decision, action).await?
+        } else {
+            0
+        };
+        let order = || self.oms.submit(strategy_id, decision, price);
+
+        // 전송 제한은 한 번만 다시 보낸다.
+        // 네트워크 오류는 주문이 들어갔을 수 있으니 당일 주문에서 먼저 찾고, 없을 때만 다시 보낸다.
+        let result = match order().await {
+            Err(e) if is_retryable(&e) => {
+                warn!("retry order {}: {}", decision.symbol, e);
+                tokio::time::sleep(ORDER_RETRY_DELAY).await;
+                order().await
+            }
+            Err(e) if is_transport(&e) => {
+                warn!("order {} unconfirmed: {}", decision.symbol, e);
+                match self.confirm(strategy_id, &decision.symbol, action).await {
+                    Ok(true) => return Ok(()),
+                    Ok(false) => {
+                        warn!("retry order {}, it was not placed", decision.symbol);
+                        order().await
+                    }
+                    Err(confirm_error) => {
+                        error!(
+                            "failed to confirm order {}: {:#}",
+                            decision.symbol, confirm_error
+                        );
+                        Err(e)
+                    }
+                }
+            }
+            result => result,
+        };
+
+        match result {
+            Ok((client_id, updates)) => {
+                log::info!("order {}: {}", client_id, decision);
+                self.record_fills(&client_id, &updates);
+            }
+            Err(e) => match e.downcast_ref::<BrokerError>() {
+                Some(BrokerError::InsufficientFunds(msg)) => {
+                    warn!("skip {}, insufficient funds: {}", decision.symbol, msg)
+                }
+                Some(BrokerError::MarketClosed(msg)) => {
+                    info!("skip {}, market closed: {}", decision.symbol, msg)
+                }
+                Some(BrokerError::InvalidTicker(msg)) => {
+                    error!("skip {}, invalid ticker: {}", decision.symbol, msg)
+                }
+                _ => return Err(e.context(format!("Failed to execute {:?} order", action))),
+            },
         }
 
         Ok(())
     }
+
+    // 전략이 `symbol` 에 `action` 으로 낸 확인 안 된 주문을 확인한다. 보내지 않은 주문이었으면 false.
+    async fn confirm(
+        &self,
+        strategy_id: &str,
+        symbol: &str,
+        action: broker::OrderAction,
+    ) -> Result<bool> {
+        let unconfirmed = self
+            .oms
+            .open_orders_by_strategy(strategy_id)
+            .await
+            .into_iter()
+            .find(|order| {
+                order.state == OrderState::Unconfirmed
+                    && order.symbol == symbol
+                    && order.action == action
+            });
+        match unconfirmed {
+            Some(order) => self.reconcile(&order.client_id).await,
+            // 그 사이 이벤트로 확인됐다.
+            None => Ok(true),
+        }
+    }
+
+    // 접수 여부를 모르는 주문을 확인하고 빠진 체결을 기록한다. 보내지 않은 주문이었으면 false.
+    async fn reconcile(&self, client_id: &str) -> Result<bool> {
+        let Some(updates) = self.oms.reconcile(client_id).await? else {
+            return Ok(false);
+        };
+        info!("order {} confirmed", client_id);
+        self.record_fills(client_id, &updates);
+        Ok(true)
+    }
+
+    fn record_fills(&self, client_id: &str, updates: &[OrderUpdate]) {
+        for update in updates {
+            if let Err(e) = record_fill(&self.position_manager, update) {
+                error!("Failed to record fill {}: {}", client_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::broker::lssec::LsSecClient;
+    use crate::broker::mock::MockLsServer;
+    use crate::broker::paper::PaperBroker;
+    use crate::broker::tradability::ViKind;
+    use crate::broker::{Broker, OrderFilter};
+    use anyhow::anyhow;
+    use std::sync::Mutex;
+
+    fn decision(price: f64) -> OrderDecision {
+        OrderDecision {
+            order_type: OrderType::Buy,
+            symbol: "005930".to_string(),
+            quantity: 1,
+            price,
+            price_type: broker::OrderType::BestLimit,
+            time_in_force: broker::TimeInForce::Ioc,
+            reason: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_adjust_for_tradability() {
+        let vi = Tradability::Vi {
+            kind: ViKind::Static,
+            trigger_price: 77000,
+        };
+        let repriced = adjust_for_tradability(&decision(70000.0), vi).unwrap();
+        assert_eq!(repriced.price_type, broker::OrderType::Limit);
+        assert_eq!(repriced.time_in_force, broker::TimeInForce::Day);
+        assert_eq!(repriced.price, 70000.0);
+
+        assert!(adjust_for_tradability(&decision(0.0), vi).is_none());
+        assert!(adjust_for_tradability(&decision(70000.0), Tradability::Halted).is_none());
+        let normal = adjust_for_tradability(&decision(70000.0), Tradability::Normal).unwrap();
+        assert_eq!(normal.price_type, broker::OrderType::BestLimit);
+    }
+
+    // 받은 체결을 기록하고 정해진 결정을 돌려주는 전략
+    struct StubStrategy {
+        id: String,
+        targets: Vec<String>,
+        order_type: Option<OrderType>,
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StubStrategy {
+        fn new(id: &str, target: &str, order_type: Option<OrderType>) -> Self {
+            Self {
+                id: id.to_string(),
+                targets: vec![target.to_string()],
+                order_type,
+                seen: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Strategy for StubStrategy {
+        fn get_id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn get_targets(&self) -> Result<Vec<String>> {
+            Ok(self.targets.clone())
+        }
+
+        async fn evaluate_tick(
+            &self,
+            tick: &Tick,
+            _position: Option<broker::Position>,
+        ) -> Result<OrderDecision> {
+            self.seen.lock().unwrap().push(tick.ticker.clone());
+            let Some(order_type) = self.order_type.clone() else {
+                return Err(anyhow!("evaluate failed"));
+            };
+            Ok(OrderDecision {
+                order_type,
+                symbol: tick.ticker.clone(),
+                quantity: 1,
+                price: tick.price as f64,
+                price_type: broker::OrderType::Limit,
+                time_in_force: broker::TimeInForce::Day,
+                reason: "test".to_string(),
+            })
+        }
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("condition not met");
+    }
+
+    #[tokio::test]
+    async fn test_run_pipeline() {
+        let server = MockLsServer::start().await.unwrap();
+        let feed = LsSecClient::new("key".to_string(), "secret".to_string())
+            .with_environment(server.environment());
+        let paper = PaperBroker::new(Arc::new(feed), 1_000_000);
+        let positions = PositionManager::new(Arc::new(paper.clone()));
+        let manager = Arc::new(TradingManager::new(paper.clone(), positions));
+
+        let failing = StubStrategy::new("failing", "005930", None);
+        let buyer = StubStrategy::new("buyer", "005930", Some(OrderType::Buy));
+        let other = StubStrategy::new("other", "005935", Some(OrderType::Hold));
+        let (failing_seen, buyer_seen, other_seen) = (
+            Arc::clone(&failing.seen),
+            Arc::clone(&buyer.seen),
+            Arc::clone(&other.seen),
+        );
+        manager.add_strategy(Box::new(failing));
+        manager.add_strategy(Box::new(buyer));
+        manager.add_strategy(Box::new(other));
+
+        let running = Arc::clone(&manager);
+        let handle = tokio::spawn(async move { running.run().await });
+        server.wait_subscribed("JIF", "0").await.unwrap();
+        server.wait_subscribed("S3_", "005930").await.unwrap();
+        server.wait_subscribed("S3_", "005935").await.unwrap();
+        server
+            .push(
+                "JIF",
+                "0",
+                serde_json::json!({"jangubun": "1", "jstatus": "21"}),
+            )
+            .await;
+        wait_until(|| {
+            manager.sessions.phase(broker::Market::KOSPI) == session::SessionPhase::Continuous
+        })
+        .await;
+        server.push_tick("005930", 70000, 10).await;
+        server.push_tick("005935", 57000, 10).await;
+
+        // 대상 종목 체결만 받는다.
+        wait_until(|| other_seen.lock().unwrap().len() == 1).await;
+        wait_until(|| buyer_seen.lock().unwrap().len() == 1).await;
+        assert_eq!(*other_seen.lock().unwrap(), vec!["005935".to_string()]);
+
+        // 한 전략이 실패해도 다른 전략의 주문은 나간다.
+        assert_eq!(*failing_seen.lock().unwrap(), vec!["005930".to_string()]);
+        for _ in 0..100 {
+            if !paper
+                .get_orders(OrderFilter::default())
+                .await
+                .unwrap()
+                .is_empty()
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let orders = paper.get_orders(OrderFilter::default()).await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].symbol, "005930");
+        assert_eq!(orders[0].price, 70000);
+        assert!(manager
+            .orders()
+            .open_orders_by_strategy("other")
+            .await
+            .is_empty());
+        handle.abort();
+    }
 }
@@ -3,30 +3,176 @@ use crate::broker::Broker;
 use crate::position::Position;
 use crate::storage::postgres::PostgresStorage;
 use anyhow::Result;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
 #[derive(Clone)]
 pub struct PositionManager {
     client: Arc<dyn Broker>,
     positions: Vec<broker::Position>,
-    storage: Arc<PostgresStorage>,
+    storage: Option<Arc<PostgresStorage>>,
+    // (전략, 종목) 별 잔고. 틱마다 DB 를 읽지 않도록 체결 때 갱신한다.
+    holdings: Arc<RwLock<HashMap<(String, String), Holding>>>,
+}
+
+// 이동평균 잔고. 다 팔면 수량과 평단을 0 으로 되돌린다.
+#[derive(Debug, Clone, Copy, Default)]
+struct Holding {
+    quantity: f64,
+    average_price: f64,
+}
+
+impl Holding {
+    fn apply(&mut self, amount: f64, price: f64) {
+        if amount > 0.0 {
+            let quantity = self.quantity + amount;
+            self.average_price = (self.quantity * self.average_price + amount * price) / quantity;
+            self.quantity = quantity;
+        } else {
+            self.quantity += amount;
+            if self.quantity <= 0.0 {
+                *self = Holding::default();
+            }
+        }
+    }
+
+    fn position(&self, ticker: &str) -> Option<broker::Position> {
+        if self.quantity <= 0.0 {
+            return None;
+        }
+        Some(broker::Position::new(
+            ticker.to_string(),
+            self.quantity as i64,
+            self.average_price,
+        ))
+    }
 }
 
 impl PositionManager {
-    pub fn new(client: Arc<dyn Broker>, storage: Arc<PostgresStorage>) -> Self {
+    pub fn new(client: Arc<dyn Broker>) -> Self {
         Self {
             client,
             positions: Vec::new(),
-            storage,
+            storage: None,
+            holdings: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// 체결을 DB 에 남기고 시작할 때 DB 에서 잔고를 읽는다.
+    pub fn with_storage(mut self, storage: Arc<PostgresStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// DB 에 기록된 체결로 전략별 잔고를 다시 만든다.
+    pub fn load(&self) -> Result<()> {
+        let mut rows = self.get_positions()?;
+        rows.sort_by_key(|p| p.created_at);
+        let mut holdings = HashMap::new();
+        for row in &rows {
+            holdings
+                .entry((row.strategy_id.clone(), row.ticker.clone()))
+                .or_insert_with(Holding::default)
+                .apply(row.amount, row.price);
+        }
+        *self.holdings.write().unwrap() = holdings;
+        Ok(())
+    }
+
     pub fn add_position(&self, position: Position) -> Result<()> {
-        self.storage.add_position(position)?;
+        // DB 기록이 실패해도 체결은 일어났으므로 잔고에는 먼저 반영한다.
+        self.holdings
+            .write()
+            .unwrap()
+            .entry((position.strategy_id.clone(), position.ticker.clone()))
+            .or_default()
+            .apply(position.amount, position.price);
+        if let Some(storage) = &self.storage {
+            storage.add_position(position)?;
+        }
         Ok(())
     }
 
     pub fn get_positions(&self) -> Result<Vec<Position>> {
-        self.storage.get_positions()
+        match &self.storage {
+            Some(storage) => storage.get_positions(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// 전략이 들고 있는 종목 잔고. 다 팔았으면 None.
+    pub fn get_position(&self, strategy_id: &str, ticker: &str) -> Option<broker::Position> {
+        self.holdings
+            .read()
+            .unwrap()
+            .get(&(strategy_id.to_string(), ticker.to_string()))
+            .and_then(|holding| holding.position(ticker))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aggregate(ticker: &str, rows: &[Position]) -> Option<broker::Position> {
+        let mut holding = Holding::default();
+        for row in rows {
+            holding.apply(row.amount, row.price);
+        }
+        holding.position(ticker)
+    }
+
+    fn row(amount: f64, price: f64) -> Position {
+        Position {
+            id: uuid::Uuid::nil(),
+            ticker: "005930".to_string(),
+            price,
+            amount,
+            strategy_id: "Envelope".to_string(),
+            created_at: chrono::Local::now().naive_local(),
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let position = aggregate(
+            "005930",
+            &[row(1.0, 70000.0), row(3.0, 71000.0), row(-2.0, 72000.0)],
+        )
+        .unwrap();
+        assert_eq!(position.quantity, 2);
+        assert_eq!(position.average_price, 70750.0);
+
+        assert!(aggregate("005930", &[row(1.0, 70000.0), row(-1.0, 71000.0)]).is_none());
+        assert!(aggregate("005930", &[]).is_none());
+    }
+
+    #[test]
+    fn test_aggregate_reentry() {
+        // 다 팔고 다시 사면 새 매수가가 평단이다.
+        let position = aggregate(
+            "005930",
+            &[row(1.0, 70000.0), row(-1.0, 75000.0), row(1.0, 80000.0)],
+        )
+        .unwrap();
+        assert_eq!(position.quantity, 1);
+        assert_eq!(position.average_price, 80000.0);
+    }
+
+    #[test]
+    fn test_get_position() {
+        let client =
+            crate::broker::lssec::LsSecClient::new("key".to_string(), "secret".to_string());
+        let manager = PositionManager::new(Arc::new(client));
+        manager.add_position(row(2.0, 70000.0)).unwrap();
+        manager.add_position(row(2.0, 72000.0)).unwrap();
+        manager.add_position(row(-1.0, 73000.0)).unwrap();
+        let position = manager.get_position("Envelope", "005930").unwrap();
+        assert_eq!(position.quantity, 3);
+        assert_eq!(position.average_price, 71000.0);
+        assert!(manager.get_position("Sample", "005930").is_none());
+
+        manager.add_position(row(-3.0, 73000.0)).unwrap();
+        assert!(manager.get_position("Envelope", "005930").is_none());
     }
 }
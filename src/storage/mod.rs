@@ -1,5 +1,5 @@
 mod analyzer;
-mod models;
+pub mod models;
 pub mod postgres;
 
 #[cfg(test)]
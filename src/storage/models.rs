@@ -1,3 +1,4 @@
+use crate::broker::Candle;
 use diesel::prelude::*;
 use uuid::Uuid;
 #[derive(Queryable, Selectable)]
@@ -9,3 +10,31 @@ pub struct Order {
     pub amount: Option<f64>,
     pub created_at: chrono::NaiveDateTime,
 }
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::charts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Chart {
+    pub ticker: String,
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: Option<f64>,
+    pub volume: Option<i32>,
+    pub datetime: chrono::NaiveDateTime,
+}
+
+impl From<&Candle> for Chart {
+    fn from(candle: &Candle) -> Self {
+        Self {
+            ticker: candle.ticker.clone(),
+            open: Some(candle.open as f64),
+            high: Some(candle.high as f64),
+            low: Some(candle.low as f64),
+            close: Some(candle.close as f64),
+            // volume 컬럼이 int4 라서 넘치면 잘라낸다.
+            volume: Some(i32::try_from(candle.volume).unwrap_or(i32::MAX)),
+            datetime: candle.datetime,
+        }
+    }
+}
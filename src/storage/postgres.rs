@@ -1,9 +1,12 @@
 use crate::position::Position;
-use crate::schema::positions;
 use crate::schema::positions::dsl::*;
+use crate::schema::{charts, positions};
+use crate::storage::models::Chart;
 use anyhow::Result;
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::upsert::excluded;
 use diesel::{Insertable, PgConnection};
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
@@ -42,6 +45,44 @@ impl PostgresStorage {
         let po = positions.select(Position::as_select()).load(con)?;
         Ok(po)
     }
+
+    /// (ticker, datetime) 가 같은 봉은 새 값으로 덮어쓴다.
+    pub fn upsert_charts(&self, rows: &[Chart]) -> Result<usize> {
+        let con = &mut self.pool.get()?;
+        let mut count = 0;
+        // 바인드 파라미터 한도(65535)를 넘지 않도록 나눠 넣는다.
+        for chunk in rows.chunks(1000) {
+            count += diesel::insert_into(charts::table)
+                .values(chunk)
+                .on_conflict((charts::ticker, charts::datetime))
+                .do_update()
+                .set((
+                    charts::open.eq(excluded(charts::open)),
+                    charts::high.eq(excluded(charts::high)),
+                    charts::low.eq(excluded(charts::low)),
+                    charts::close.eq(excluded(charts::close)),
+                    charts::volume.eq(excluded(charts::volume)),
+                ))
+                .execute(con)?;
+        }
+        Ok(count)
+    }
+
+    pub fn get_charts(
+        &self,
+        symbol: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Chart>> {
+        let con = &mut self.pool.get()?;
+        let rows = charts::table
+            .select(Chart::as_select())
+            .filter(charts::ticker.eq(symbol))
+            .filter(charts::datetime.between(from, to))
+            .order(charts::datetime.asc())
+            .load(con)?;
+        Ok(rows)
+    }
 }
 
 #[cfg(test)]
@@ -81,6 +122,34 @@ mod test {
         println!("{:?}", result);
     }
 
+    #[test]
+    fn test_upsert_charts() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let po = PostgresStorage::new(database_url);
+        let at = chrono::NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let mut chart = Chart {
+            ticker: "TEST".to_string(),
+            open: Some(100.0),
+            high: Some(110.0),
+            low: Some(90.0),
+            close: Some(105.0),
+            volume: Some(1000),
+            datetime: at,
+        };
+        po.upsert_charts(&[chart.clone()])
+            .expect("Failed to insert chart");
+        chart.close = Some(107.0);
+        po.upsert_charts(&[chart.clone()])
+            .expect("Failed to update chart");
+
+        let result = po.get_charts("TEST", at, at).expect("");
+        assert_eq!(result, vec![chart]);
+    }
+
     #[test]
     fn test_get_positions() {
         dotenv().ok();
@@ -3,6 +3,7 @@ from datetime import datetime
 from dateutil.relativedelta import relativedelta
 from talib import abstract
 import FinanceDataReader as fdr
+import pandas as pd
 from functools import lru_cache
 
 
@@ -10,6 +11,20 @@ class Envolope:
 
     def __init__(self):
         self.dfs = {}
+        end = datetime.now()
+        self.start = (end - relativedelta(years=1)).strftime('%Y-%m-%d')
+        self.end = end.strftime('%Y-%m-%d')
+
+    def set_window(self, start: str, end: str):
+        """
+        일봉 조회 구간. 영업일 달력으로 계산한 값을 받는다.
+        :param start: 시작일 (YYYY-MM-DD)
+        :param end: 마지막 영업일 (YYYY-MM-DD)
+        """
+        if (start, end) != (self.start, self.end):
+            self.dfs = {}
+        self.start = start
+        self.end = end
 
     def get_top100_amount_ticker(self, date: str) -> list:
         """
@@ -22,10 +37,8 @@ class Envolope:
         df.sort_values(by='거래대금', ascending=False, inplace=True)
         return list(df[:100].index.tolist())
 
-    def update_df(self,ticker):
-        end = datetime.now().strftime('%Y-%m-%d')
-        start = datetime.now() - relativedelta(years=1)
-        df = fdr.DataReader(ticker, start, end)
+    @staticmethod
+    def indicators(df):
         df['prev_close'] = df['Close'].shift(1)
         df['diff'] = (df['Close'] - df['prev_close']) / df['prev_close'] * 100
         df['moving20'] = abstract.SMA(df, timeperiod=20, price='Close')
@@ -33,12 +46,27 @@ class Envolope:
         df['moving7'] = abstract.SMA(df, timeperiod=7, price='Close')
         df['upper'] = df['moving20'] * 1.4
         df['mask_upper_cross'] = df['High'] > df['upper']
-        self.dfs[ticker] = df
+        return df
+
+    def update_df(self,ticker):
+        df = fdr.DataReader(ticker, self.start, self.end)
+        self.dfs[ticker] = self.indicators(df)
+
+    def set_history(self, ticker, rows):
+        """
+        charts 테이블에서 읽은 일봉으로 지표를 계산한다.
+        :param rows: [날짜, 시가, 고가, 저가, 종가, 거래량] 목록
+        """
+        df = pd.DataFrame(rows, columns=['Date', 'Open', 'High', 'Low', 'Close', 'Volume'])
+        df['Date'] = pd.to_datetime(df['Date'])
+        df.set_index('Date', inplace=True)
+        self.dfs[ticker] = self.indicators(df)
 
-    @lru_cache(maxsize=128)
     def target(self):
-        end = datetime.now().strftime('%Y-%m-%d')
-        start = datetime.now() - relativedelta(years=1)
+        return self._target(self.start, self.end)
+
+    @lru_cache(maxsize=128)
+    def _target(self, start, end):
         tk = self.get_top100_amount_ticker(end)
         items = []
         for i in tk:
@@ -1,14 +1,37 @@
 use crate::broker;
+use crate::broker::calendar::TradingCalendar;
+use crate::broker::session::kst_now;
 use crate::broker::Tick;
+use crate::storage::postgres::PostgresStorage;
 use crate::strategies::strategy_base::Strategy;
 use crate::strategies::strategy_base::{OrderDecision, OrderType};
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveTime};
 use pyo3::prelude::*;
 use pyo3::{Py, PyAny, PyResult, Python};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+// 날짜, 시가, 고가, 저가, 종가, 거래량
+type HistoryRow = (String, f64, f64, f64, f64, i32);
+
+// 지표 계산에 쓰는 일봉 수 (약 1년)
+const HISTORY_TRADING_DAYS: usize = 250;
+
+// 파이썬 쪽에 일봉을 넘긴 종목. 파이썬 dfs 처럼 조회 구간이 바뀌면 비운다.
+#[derive(Default)]
+struct HistoryCache {
+    window: Option<(NaiveDate, NaiveDate)>,
+    tickers: HashSet<String>,
+}
 
 pub struct Envelope {
+    // 전략이 살아 있는 동안 같은 인스턴스를 써서 파이썬 쪽 캐시를 유지한다.
     app: Py<PyAny>,
+    storage: Option<Arc<PostgresStorage>>,
+    calendar: TradingCalendar,
+    loaded: Mutex<HistoryCache>,
 }
 
 impl Envelope {
@@ -22,10 +45,94 @@ impl Envelope {
                 .unwrap()
                 .getattr("Envolope")
                 .unwrap()
+                .call0()
+                .unwrap()
                 .into()
         });
 
-        Self { app }
+        Self {
+            app,
+            storage: None,
+            calendar: TradingCalendar::krx(),
+            loaded: Mutex::new(HistoryCache::default()),
+        }
+    }
+
+    /// 지표 계산에 FinanceDataReader 대신 charts 테이블의 일봉을 쓴다.
+    pub fn with_storage(mut self, storage: Arc<PostgresStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn with_calendar(mut self, calendar: TradingCalendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    // 확정된 마지막 일봉까지 영업일 기준 HISTORY_TRADING_DAYS 개
+    fn window(&self) -> (NaiveDate, NaiveDate) {
+        let to = self.calendar.last_closed_day(kst_now());
+        let from = self
+            .calendar
+            .trading_days_before(to, HISTORY_TRADING_DAYS - 1);
+        (from, to)
+    }
+
+    // 파이썬 인스턴스에 조회 구간을 넘긴다. 구간이 바뀌면 파이썬 쪽에서 지표를 다시 계산한다.
+    fn instance(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let (from, to) = self.window();
+        self.app
+            .call_method1(py, "set_window", (from.to_string(), to.to_string()))?;
+        Ok(self.app.clone_ref(py))
+    }
+
+    // 최근 1년 일봉. 종목마다 조회 구간에 한 번만 읽는다.
+    // 이미 넘긴 종목이면 None, 저장된 봉이 없어도 None 을 돌려 파이썬 쪽에서 내려받게 한다.
+    async fn history(&self, symbol: &str) -> Result<Option<Vec<HistoryRow>>> {
+        let Some(storage) = &self.storage else {
+            return Ok(None);
+        };
+        let (from, to) = self.window();
+        {
+            let mut loaded = self.loaded.lock().unwrap();
+            if loaded.window != Some((from, to)) {
+                loaded.window = Some((from, to));
+                loaded.tickers.clear();
+            }
+            if loaded.tickers.contains(symbol) {
+                return Ok(None);
+            }
+        }
+        let storage = Arc::clone(storage);
+        let ticker = symbol.to_string();
+        let charts = tokio::task::spawn_blocking(move || {
+            storage.get_charts(
+                &ticker,
+                from.and_time(NaiveTime::MIN),
+                to.and_time(NaiveTime::MIN),
+            )
+        })
+        .await??;
+        let rows: Vec<HistoryRow> = charts
+            .into_iter()
+            .filter(|chart| chart.datetime.time() == NaiveTime::MIN)
+            .map(|chart| {
+                (
+                    chart.datetime.date().to_string(),
+                    chart.open.unwrap_or(f64::NAN),
+                    chart.high.unwrap_or(f64::NAN),
+                    chart.low.unwrap_or(f64::NAN),
+                    chart.close.unwrap_or(f64::NAN),
+                    chart.volume.unwrap_or(0),
+                )
+            })
+            .collect();
+        self.loaded
+            .lock()
+            .unwrap()
+            .tickers
+            .insert(symbol.to_string());
+        Ok(Some(rows).filter(|rows| !rows.is_empty()))
     }
 }
 
@@ -35,13 +142,13 @@ impl Strategy for Envelope {
         return "Envelope".to_string();
     }
 
-    fn get_targets(&self) -> Vec<String> {
-        Python::with_gil(|py| -> PyResult<Vec<String>> {
-            let instance = self.app.call0(py)?;
+    fn get_targets(&self) -> Result<Vec<String>> {
+        let targets = Python::with_gil(|py| -> PyResult<Vec<String>> {
+            let instance = self.instance(py)?;
             let target: Vec<String> = instance.call_method0(py, "target")?.extract(py)?;
             Ok(target)
-        })
-        .expect("Failed to get targets from python")
+        })?;
+        Ok(targets)
     }
 
     async fn evaluate_tick(
@@ -50,12 +157,16 @@ impl Strategy for Envelope {
         position: Option<broker::Position>,
     ) -> Result<OrderDecision> {
         let symbol = &tick.ticker;
-        let price: f64 = tick.price.parse()?;
+        let price = tick.price as f64;
+        let history = self.history(symbol).await?;
 
         match position {
             Some(p) => {
                 let sell = Python::with_gil(|py| -> PyResult<bool> {
-                    let instance = self.app.call0(py)?;
+                    let instance = self.instance(py)?;
+                    if let Some(rows) = &history {
+                        instance.call_method1(py, "set_history", (symbol, rows.clone()))?;
+                    }
                     let target: bool = instance
                         .call_method1(py, "sell", (symbol, price, p.average_price))?
                         .extract(py)?;
@@ -68,6 +179,8 @@ impl Strategy for Envelope {
                         symbol: tick.ticker.clone(),
                         quantity: 1,
                         price: price,
+                        price_type: broker::OrderType::Market,
+                        time_in_force: broker::TimeInForce::Day,
                         reason: "Buy signal detected".to_string(),
                     });
                 }
@@ -76,12 +189,17 @@ impl Strategy for Envelope {
                     symbol: tick.ticker.clone(),
                     quantity: 1,
                     price: price,
+                    price_type: broker::OrderType::Market,
+                    time_in_force: broker::TimeInForce::Day,
                     reason: "Hold signal detected".to_string(),
                 })
             }
             None => {
                 let buy = Python::with_gil(|py| -> PyResult<bool> {
-                    let instance = self.app.call0(py)?;
+                    let instance = self.instance(py)?;
+                    if let Some(rows) = &history {
+                        instance.call_method1(py, "set_history", (symbol, rows.clone()))?;
+                    }
                     let target: bool = instance
                         .call_method1(py, "buy", (symbol, price))?
                         .extract(py)?;
@@ -94,6 +212,8 @@ impl Strategy for Envelope {
                         symbol: tick.ticker.clone(),
                         quantity: 1,
                         price: price,
+                        price_type: broker::OrderType::Market,
+                        time_in_force: broker::TimeInForce::Day,
                         reason: "Buy signal detected".to_string(),
                     });
                 }
@@ -102,6 +222,8 @@ impl Strategy for Envelope {
                     symbol: tick.ticker.clone(),
                     quantity: 1,
                     price: price,
+                    price_type: broker::OrderType::Market,
+                    time_in_force: broker::TimeInForce::Day,
                     reason: "Hold signal detected".to_string(),
                 })
             }
@@ -125,8 +247,8 @@ mod test {
     async fn test_buy() -> Result<()> {
         pyo3::prepare_freethreaded_python();
         let env = Envelope::new();
-        println!("{}", env.get_targets().len());
-        // let _ = env.evaluate_tick(&Tick::new("005930".to_string(), "100".to_string(), "100".to_string())).await?;
+        println!("{}", env.get_targets()?.len());
+        // let _ = env.evaluate_tick(&Tick::new("005930".to_string(), 100, 100)).await?;
         Ok(())
     }
 
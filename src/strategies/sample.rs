@@ -1,5 +1,5 @@
-use crate::broker::{Position, Tick};
-use crate::strategies::strategy_base::{OrderDecision, Strategy};
+use crate::broker::{self, Position, Tick};
+use crate::strategies::strategy_base::{OrderDecision, OrderType, Strategy};
 use async_trait::async_trait;
 
 pub struct SampleStrategy {}
@@ -16,15 +16,24 @@ impl Strategy for SampleStrategy {
         "sample".to_string()
     }
 
-    fn get_targets(&self) -> Vec<String> {
-        todo!()
+    // 예제 전략이라 대상 종목이 없다.
+    fn get_targets(&self) -> anyhow::Result<Vec<String>> {
+        Ok(Vec::new())
     }
 
     async fn evaluate_tick(
         &self,
         tick: &Tick,
-        position: Option<Position>,
+        _position: Option<Position>,
     ) -> anyhow::Result<OrderDecision> {
-        todo!()
+        Ok(OrderDecision {
+            order_type: OrderType::Hold,
+            symbol: tick.ticker.clone(),
+            quantity: 0,
+            price: tick.price as f64,
+            price_type: broker::OrderType::Market,
+            time_in_force: broker::TimeInForce::Day,
+            reason: "sample".to_string(),
+        })
     }
 }
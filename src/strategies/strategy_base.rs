@@ -1,5 +1,5 @@
 use crate::broker;
-use crate::broker::Tick;
+use crate::broker::{Market, OrderBook, Tick};
 use anyhow::Result;
 use async_trait::async_trait;
 use std::fmt::Display;
@@ -7,12 +7,27 @@ use std::fmt::Display;
 #[async_trait]
 pub trait Strategy: Send + Sync {
     fn get_id(&self) -> String;
-    fn get_targets(&self) -> Vec<String>;
+    fn get_targets(&self) -> anyhow::Result<Vec<String>>;
     async fn evaluate_tick(
         &self,
         tick: &Tick,
         position: Option<broker::Position>,
     ) -> Result<OrderDecision>;
+    /// 호가를 받을 종목. 기본은 없음
+    fn get_order_book_targets(&self) -> Vec<String> {
+        Vec::new()
+    }
+    async fn on_order_book(&self, _book: &OrderBook) -> Result<()> {
+        Ok(())
+    }
+    /// 정규장 시작
+    async fn on_market_open(&self, _market: Market) -> Result<()> {
+        Ok(())
+    }
+    /// 장 마감
+    async fn on_market_close(&self, _market: Market) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +36,9 @@ pub struct OrderDecision {
     pub symbol: String,
     pub quantity: u32,
     pub price: f64,
+    //지정가, 시장가 등 호가 유형
+    pub price_type: broker::OrderType,
+    pub time_in_force: broker::TimeInForce,
     pub reason: String,
 }
 
@@ -28,8 +46,14 @@ impl Display for OrderDecision {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "symbol: {}, order_type: {:?}, quantity: {}, price: {}, reason: {}",
-            self.symbol, self.order_type, self.quantity, self.price, self.reason
+            "symbol: {}, order_type: {:?}, quantity: {}, price: {}, price_type: {:?}, time_in_force: {:?}, reason: {}",
+            self.symbol,
+            self.order_type,
+            self.quantity,
+            self.price,
+            self.price_type,
+            self.time_in_force,
+            self.reason
         )
     }
 }